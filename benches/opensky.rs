@@ -0,0 +1,116 @@
+//! Cost of one OpenSky state response of 10k aircraft, about the size of a global `/states/all`.
+
+use bevy::prelude::*;
+use bevy_http_client::ehttp::{Headers, Response};
+use bevy_http_client::HttpResponse;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use serde_json::json;
+use tacview_live::opensky::bench::state_app;
+use tacview_live::opensky::OpenSkyKey;
+use tacview_live::track::TrackIndex;
+
+const AIRCRAFT: usize = 10_000;
+
+fn icao24(i: usize) -> String {
+    format!("{:06x}", 0x400000 + i)
+}
+
+/// synthetic states spread over the globe, `time` moves every aircraft
+fn states_response(time: u64) -> HttpResponse {
+    let states = (0..AIRCRAFT)
+        .map(|i| {
+            let offset = (time % 60) as f64 * 0.001;
+            json!([
+                icao24(i),
+                format!("TST{:04} ", i % 10_000),
+                "Testland",
+                time,
+                time,
+                (i % 360) as f64 - 180.0 + offset,
+                (i % 170) as f64 - 85.0,
+                10_000.0,
+                false,
+                230.0,
+                (i % 360) as f64,
+                0.0,
+                null,
+                10_200.0,
+                "1000",
+                false,
+                0,
+                (i % 8) as u32
+            ])
+        })
+        .collect::<Vec<_>>();
+    let body = json!({ "time": time, "states": states });
+    HttpResponse(Response {
+        url: "https://opensky-network.org/api/states/all".to_string(),
+        ok: true,
+        status: 200,
+        status_text: "OK".to_string(),
+        headers: Headers::new(&[("content-type", "application/json")]),
+        bytes: serde_json::to_vec(&body).unwrap(),
+    })
+}
+
+fn state_response(c: &mut Criterion) {
+    let mut group = c.benchmark_group("handle_state_response");
+    group.sample_size(20);
+
+    let response = states_response(1_717_000_000);
+    group.bench_function("spawn 10k", |b| {
+        b.iter_batched(
+            || {
+                let mut app = state_app();
+                app.world.send_event(response.clone());
+                app
+            },
+            |mut app| {
+                app.update();
+                app
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    let mut app = state_app();
+    app.world.send_event(states_response(1_717_000_000));
+    app.update();
+    let mut time = 1_717_000_000;
+    group.bench_function("update 10k", |b| {
+        b.iter_batched(
+            || {
+                time += 1;
+                states_response(time)
+            },
+            |response| {
+                app.world.send_event(response);
+                app.update();
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn index(c: &mut Criterion) {
+    let mut world = World::new();
+    let mut index = TrackIndex::<OpenSkyKey>::default();
+    for i in 0..AIRCRAFT {
+        index.insert(OpenSkyKey(icao24(i)), world.spawn_empty().id());
+    }
+    let keys = (0..AIRCRAFT)
+        .map(|i| OpenSkyKey(icao24(i)))
+        .collect::<Vec<_>>();
+
+    c.bench_function("track_index lookup 10k", |b| {
+        b.iter(|| {
+            keys.iter()
+                .filter(|key| index.get(black_box(*key)).is_some())
+                .count()
+        })
+    });
+}
+
+criterion_group!(benches, state_response, index);
+criterion_main!(benches);
@@ -0,0 +1,196 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_tacview::record::{Coords, Property};
+
+use crate::opensky::category_tags;
+use crate::track::{TrackKey, TrackPlugin, TrackSource, TrackState};
+
+/// Aircraft of the local ADS-B feeds, keyed by ICAO24 address and shared by every feed so the
+/// same receiver read through several of them shows each aircraft once.
+pub struct AdsbPlugin;
+
+impl Plugin for AdsbPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<AdsbAircraft>()
+            .add_plugins(TrackPlugin::<AdsbAircraft>::default());
+    }
+}
+
+/// ICAO 24-bit address in lower case hex, `~` prefixed for non-ICAO addresses
+#[derive(Debug, Component, Reflect, Clone, PartialEq, Eq, Hash)]
+pub struct AdsbKey(pub String);
+
+impl TrackKey for AdsbKey {}
+
+/// Accumulated state of one aircraft, every message only carries some of the fields.
+#[derive(Debug, Component, Reflect, Clone, PartialEq)]
+pub struct AdsbAircraft {
+    /// ICAO 24-bit address in lower case hex, `~` prefixed for non-ICAO addresses
+    pub icao24: String,
+    pub callsign: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// barometric altitude in meters
+    pub baro_altitude: Option<f64>,
+    /// geometric altitude in meters
+    pub geo_altitude: Option<f64>,
+    pub on_ground: bool,
+    /// ground speed in m/s
+    pub ground_speed: Option<f64>,
+    /// track over ground in degrees clockwise from north
+    pub track: Option<f64>,
+    /// vertical rate in m/s, positive when climbing
+    pub vertical_rate: Option<f64>,
+    pub squawk: Option<String>,
+    /// ADS-B emitter category, numbered like the OpenSky category field
+    pub category: Option<u32>,
+    /// emergency status, e.g. general or lifeguard
+    pub emergency: Option<String>,
+}
+
+/// The fields one message of a feed carries, converted to SI units.
+#[derive(Debug, Default, PartialEq)]
+pub struct AdsbMessage {
+    /// ICAO 24-bit address in lower case hex, `~` prefixed for non-ICAO addresses
+    pub icao24: String,
+    pub callsign: Option<String>,
+    /// only applied together with the longitude
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub baro_altitude: Option<f64>,
+    pub geo_altitude: Option<f64>,
+    pub on_ground: Option<bool>,
+    pub ground_speed: Option<f64>,
+    pub track: Option<f64>,
+    pub vertical_rate: Option<f64>,
+    pub squawk: Option<String>,
+    pub category: Option<u32>,
+    /// `"none"` clears the emergency status
+    pub emergency: Option<String>,
+}
+
+impl TrackState for AdsbAircraft {
+    type Key = AdsbKey;
+    type Message = AdsbMessage;
+
+    fn key(message: &AdsbMessage) -> AdsbKey {
+        AdsbKey(message.icao24.clone())
+    }
+
+    fn new(message: AdsbMessage) -> Option<Self> {
+        let mut aircraft = Self {
+            icao24: message.icao24.clone(),
+            callsign: None,
+            latitude: None,
+            longitude: None,
+            baro_altitude: None,
+            geo_altitude: None,
+            on_ground: false,
+            ground_speed: None,
+            track: None,
+            vertical_rate: None,
+            squawk: None,
+            category: None,
+            emergency: None,
+        };
+        aircraft.merge(message);
+        Some(aircraft)
+    }
+
+    fn merge(&mut self, message: AdsbMessage) {
+        if message.callsign.is_some() {
+            self.callsign = message.callsign;
+        }
+        if let (Some(latitude), Some(longitude)) = (message.latitude, message.longitude) {
+            self.latitude = Some(latitude);
+            self.longitude = Some(longitude);
+        }
+        if message.baro_altitude.is_some() {
+            self.baro_altitude = message.baro_altitude;
+        }
+        if message.geo_altitude.is_some() {
+            self.geo_altitude = message.geo_altitude;
+        }
+        if let Some(on_ground) = message.on_ground {
+            self.on_ground = on_ground;
+        }
+        if message.ground_speed.is_some() {
+            self.ground_speed = message.ground_speed;
+        }
+        if message.track.is_some() {
+            self.track = message.track;
+        }
+        if message.vertical_rate.is_some() {
+            self.vertical_rate = message.vertical_rate;
+        }
+        if message.squawk.is_some() {
+            self.squawk = message.squawk;
+        }
+        if message.category.is_some() {
+            self.category = message.category;
+        }
+        if let Some(emergency) = message.emergency {
+            self.emergency = (emergency != "none").then_some(emergency);
+        }
+    }
+}
+
+impl TrackSource for AdsbAircraft {
+    type Key = AdsbKey;
+    type Data = &'static AdsbAircraft;
+    type Changed = Changed<AdsbAircraft>;
+
+    const TIMEOUT: Option<Duration> = Some(Duration::from_secs(60));
+
+    /// only shown once a position was decoded, Tacview would place it at 0,0 until then
+    fn ready(aircraft: &&AdsbAircraft) -> bool {
+        aircraft.latitude.is_some() && aircraft.longitude.is_some()
+    }
+
+    fn coords(aircraft: &&AdsbAircraft) -> Coords {
+        Coords {
+            longitude: aircraft.longitude,
+            latitude: aircraft.latitude,
+            altitude: aircraft.baro_altitude.or(aircraft.geo_altitude),
+            u: None,
+            v: None,
+            roll: Some(0.0),
+            pitch: Some(0.0),
+            yaw: aircraft.track,
+            heading: None,
+        }
+    }
+
+    fn props(aircraft: &&AdsbAircraft) -> Vec<Property> {
+        let mut list = vec![
+            Property::Name(aircraft.icao24.clone()),
+            Property::Type(category_tags(aircraft.category)),
+        ];
+
+        if !aircraft.icao24.starts_with('~') {
+            list.push(Property::ICAO24(aircraft.icao24.clone()));
+        }
+
+        if let Some(call_sign) = aircraft.callsign.as_ref() {
+            list.push(Property::CallSign(call_sign.clone()));
+        }
+        if let Some(squawk) = aircraft.squawk.as_ref() {
+            list.push(Property::Squawk(squawk.clone()));
+        }
+        if let Some(ground_speed) = aircraft.ground_speed {
+            list.push(Property::Unknown(
+                "GroundSpeed".to_string(),
+                format!("{:.1}", ground_speed),
+            ));
+        }
+        if let Some(emergency) = aircraft.emergency.as_ref() {
+            list.push(Property::Unknown(
+                "Emergency".to_string(),
+                emergency.clone(),
+            ));
+        }
+
+        list
+    }
+}
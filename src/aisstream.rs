@@ -1,30 +1,71 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use bevy::prelude::*;
-use bevy_activation::ActiveState;
+use bevy::{ecs::query::QueryData, prelude::*};
 use bevy_inspector_egui::quick::ResourceInspectorPlugin;
 use bevy_octopus::prelude::*;
-use bevy_tacview::record::{Coords, Property, PropertyList, Tag};
-use bevy_tacview::systems::ObjectNeedSync;
+use bevy_tacview::record::{Coords, Property, Tag};
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Deserializer, Serialize};
 
-use crate::opensky::StateVector;
+use crate::config::Config;
+use crate::network::log_event;
+use crate::track::{TrackIndex, TrackKey, TrackPlugin, TrackSource};
+use crate::units::KNOT;
 
 const AISSTREAM_CHANNEL: ChannelId = ChannelId("AIS");
 
+/// first reconnect delay, doubled with every failed attempt
+const RECONNECT_BASE: Duration = Duration::from_secs(1);
+/// longest wait between two connection attempts
+const RECONNECT_MAX: Duration = Duration::from_secs(60);
+
 pub struct AISStreamPlugin;
 
 impl Plugin for AISStreamPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<MSSIIndex>()
-            .register_type::<MetaData>()
-            .register_type::<PositionReport>()
-            .register_type::<MSSIIndex>()
-            .add_plugins(ResourceInspectorPlugin::<MSSIIndex>::default())
+        if !app.is_plugin_added::<VesselPlugin>() {
+            app.add_plugins(VesselPlugin);
+        }
+        app.init_resource::<AISStreamConnection>()
+            .register_type::<AISStreamConnection>()
+            .add_event::<UpdateAisSubscription>()
             .add_systems(Startup, setup)
-            .add_systems(Update, (handle_connect, handle_raw_packet))
-            .add_systems(Update, (watch_added, watch_changed));
+            .add_systems(
+                Update,
+                (
+                    handle_connect,
+                    reconnect,
+                    update_connection_times,
+                    watch_config.run_if(resource_changed::<Config>),
+                    update_subscription,
+                    handle_raw_packet,
+                ),
+            );
+
+        if app.is_plugin_added::<WindowPlugin>() {
+            app.add_plugins(ResourceInspectorPlugin::<AISStreamConnection>::default());
+        }
+    }
+}
+
+/// Vessel entities keyed by MMSI, shared by every AIS source.
+pub struct VesselPlugin;
+
+impl Plugin for VesselPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<MetaData>()
+            .register_type::<PositionReport>()
+            .register_type::<ClassBPositionReport>()
+            .register_type::<AidsToNavigationReport>()
+            .register_type::<ShipStaticData>()
+            .register_type::<StaticDataReport>()
+            .add_plugins(TrackPlugin::<Vessel>::default())
+            .add_plugins(TrackPlugin::<AidToNavigation>::default());
+
+        if app.is_plugin_added::<WindowPlugin>() {
+            app.add_plugins(ResourceInspectorPlugin::<TrackIndex<VesselKey>>::default());
+        }
     }
 }
 
@@ -32,20 +73,150 @@ impl Plugin for AISStreamPlugin {
 #[derive(Resource)]
 pub struct AISStreamResource {
     pub api_key: String,
+    /// websocket endpoint, e.g. wss://stream.aisstream.io/v0/stream
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Reflect)]
+pub enum ConnectionState {
+    #[default]
+    Connecting,
+    Connected,
+    /// waiting for the next attempt
+    Disconnected,
+}
+
+/// State of the AISStream websocket, shown in the inspector.
+#[derive(Resource, Reflect, Default, Debug)]
+pub struct AISStreamConnection {
+    pub state: ConnectionState,
+    /// seconds since the current connection was established
+    pub uptime: u64,
+    /// connection attempts after the first one
+    pub reconnects: u32,
+    /// failed attempts in a row
+    pub failures: u32,
+    /// seconds until the next attempt
+    pub retry_in: u64,
+    #[reflect(ignore)]
+    pub connected_at: Option<Instant>,
+    #[reflect(ignore)]
+    pub retry_at: Option<Instant>,
+    #[reflect(ignore)]
+    pub jitter: Jitter,
+}
+
+/// Xorshift generator of the reconnect jitter, seeded from the clock. Tests seed it themselves
+/// to get the same delays on every run.
+#[derive(Debug, Clone)]
+pub struct Jitter(u64);
+
+impl Default for Jitter {
+    fn default() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        Self::seeded(nanos)
+    }
+}
+
+impl Jitter {
+    pub fn seeded(seed: u64) -> Self {
+        // xorshift gets stuck on zero
+        Self(seed | 1)
+    }
+
+    /// next factor in `0.0..1.0`
+    fn next(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl AISStreamConnection {
+    /// Schedule the next attempt with exponential backoff, the jitter keeps many instances from
+    /// reconnecting in lockstep after an outage.
+    fn schedule_retry(&mut self) {
+        self.failures += 1;
+        let backoff = RECONNECT_BASE
+            .saturating_mul(2u32.saturating_pow(self.failures - 1))
+            .min(RECONNECT_MAX);
+        let delay = backoff.mul_f64(0.5 + self.jitter.next() / 2.0);
+        self.state = ConnectionState::Disconnected;
+        self.connected_at = None;
+        self.retry_at = Some(Instant::now() + delay);
+    }
 }
 
 /// setup the connection to AISStream
-fn setup(mut commands: Commands) {
-    commands.spawn((
-        AISSTREAM_CHANNEL,
-        ConnectTo::new("wss://stream.aisstream.io/v0/stream"),
-    ));
+fn setup(res: Res<AISStreamResource>, mut commands: Commands) {
+    commands.spawn((AISSTREAM_CHANNEL, ConnectTo::new(&res.url)));
+}
+
+/// Re-send the subscription built from the current [`Config`] without reconnecting, AISStream
+/// replaces the areas and filters of the open connection.
+#[derive(Event, Debug, Default)]
+pub struct UpdateAisSubscription;
+
+/// The subscription message, sent again on every reconnect and [`UpdateAisSubscription`].
+fn subscription(res: &AISStreamResource, config: &Config) -> String {
+    let bounding_boxes = config
+        .areas
+        .iter()
+        .map(|area| [[area.min_lat, area.min_lon], [area.max_lat, area.max_lon]])
+        .collect::<Vec<_>>();
+    let mut sub = serde_json::json!({
+        "APIKey": res.api_key,
+        "BoundingBoxes": bounding_boxes,
+    });
+    if !config.aisstream.mmsi.is_empty() {
+        sub["FiltersShipMMSI"] = config
+            .aisstream
+            .mmsi
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .into();
+    }
+    if !config.aisstream.message_types.is_empty() {
+        sub["FilterMessageTypes"] = config.aisstream.message_types.clone().into();
+    }
+    sub.to_string()
+}
+
+fn watch_config(mut ev_update: EventWriter<UpdateAisSubscription>) {
+    ev_update.send(UpdateAisSubscription);
+}
+
+/// Send the current subscription, a connection that is not up yet sends it once connected.
+fn update_subscription(
+    mut ev_update: EventReader<UpdateAisSubscription>,
+    res: Res<AISStreamResource>,
+    config: Res<Config>,
+    connection: Res<AISStreamConnection>,
+    q_server: Query<(&ChannelId, &NetworkNode)>,
+) {
+    if ev_update.read().count() == 0 || connection.state != ConnectionState::Connected {
+        return;
+    }
+    for (channel_id, net_node) in q_server.iter() {
+        if *channel_id == AISSTREAM_CHANNEL {
+            info!("update {} subscription", channel_id);
+            net_node.send_text(subscription(&res, &config));
+        }
+    }
 }
 
 fn handle_connect(
     res: Res<AISStreamResource>,
+    config: Res<Config>,
+    mut connection: ResMut<AISStreamConnection>,
     mut ev_node: EventReader<NetworkNodeEvent>,
     q_net_node: Query<&NetworkNode>,
+    mut commands: Commands,
 ) {
     for NetworkNodeEvent {
         node: entity,
@@ -57,29 +228,77 @@ fn handle_connect(
             continue;
         }
 
-        match event {
-            NetworkEvent::Connected => {
-                info!("{channel_id} Connected");
-                let node = q_net_node.get(*entity).unwrap();
-                let sub = serde_json::json!({
-                    "APIKey": res.api_key,
-                    "BoundingBoxes": [[[3.2063329870791444, 97.4267578125], [29.477861195816843, 141.48193359375003 ]]],
-                    // "BoundingBoxes": [[[97.4267578125, 3.2063329870791444], [141.48193359375003, 29.477861195816843]]],
-                    // "FilterMessageTypes": ["PositionReport"]
-                });
-                node.send_text(sub.to_string())
-            }
-            NetworkEvent::Disconnected => {
-                info!("Disconnected from {}", channel_id);
+        log_event(channel_id, event);
+        if let NetworkEvent::Connected = event {
+            match q_net_node.get(*entity) {
+                Ok(node) => node.send_text(subscription(&res, &config)),
+                Err(e) => error!("no node to subscribe on {}: {:?}", channel_id, e),
             }
-            NetworkEvent::Listen => {}
-            NetworkEvent::Error(error) => {
-                error!("Error on {}: {:?}", channel_id, error);
+            connection.state = ConnectionState::Connected;
+            connection.connected_at = Some(Instant::now());
+            connection.failures = 0;
+            connection.retry_at = None;
+        }
+
+        // an error may be followed by a disconnect, schedule only one attempt
+        if matches!(event, NetworkEvent::Disconnected | NetworkEvent::Error(_))
+            && connection.state != ConnectionState::Disconnected
+        {
+            connection.schedule_retry();
+            info!(
+                "reconnect to {} in {:.1}s",
+                channel_id,
+                connection
+                    .retry_at
+                    .map(|retry_at| retry_at.duration_since(Instant::now()).as_secs_f64())
+                    .unwrap_or_default()
+            );
+            if let Some(mut node) = commands.get_entity(*entity) {
+                node.despawn();
             }
         }
     }
 }
 
+/// Open a new connection once the backoff is over.
+fn reconnect(
+    res: Res<AISStreamResource>,
+    mut connection: ResMut<AISStreamConnection>,
+    mut commands: Commands,
+) {
+    if connection.state != ConnectionState::Disconnected
+        || connection
+            .retry_at
+            .is_some_and(|retry_at| Instant::now() < retry_at)
+    {
+        return;
+    }
+    info!("reconnecting to {}", res.url);
+    connection.state = ConnectionState::Connecting;
+    connection.reconnects += 1;
+    connection.retry_at = None;
+    commands.spawn((AISSTREAM_CHANNEL, ConnectTo::new(&res.url)));
+}
+
+fn update_connection_times(mut connection: ResMut<AISStreamConnection>) {
+    let now = Instant::now();
+    let uptime = connection
+        .connected_at
+        .map(|connected_at| now.duration_since(connected_at).as_secs())
+        .unwrap_or_default();
+    let retry_in = connection
+        .retry_at
+        .map(|retry_at| retry_at.saturating_duration_since(now).as_secs())
+        .unwrap_or_default();
+    // only touch the resource when the shown seconds change
+    if connection.uptime != uptime {
+        connection.uptime = uptime;
+    }
+    if connection.retry_in != retry_in {
+        connection.retry_in = retry_in;
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(untagged)]
 enum AuthMessage {
@@ -94,42 +313,105 @@ struct AuthError {
 
 type Message = serde_json::Value;
 
-#[derive(Resource, Default, Deref, Reflect, DerefMut)]
-struct MSSIIndex(HashMap<i32, Entity>);
+/// MMSI of a vessel or aid to navigation
+#[derive(Debug, Component, Reflect, Clone, PartialEq, Eq, Hash)]
+pub struct VesselKey(pub i32);
+
+impl TrackKey for VesselKey {}
+
+/// the decoded `Message` part of an AISStream message
+pub(crate) enum AISMessageBody {
+    PositionReport(PositionReport),
+    ClassBPositionReport(ClassBPositionReport),
+    ShipStaticData(ShipStaticData),
+    StaticDataReport(StaticDataReport),
+    AidsToNavigationReport(AidsToNavigationReport),
+    Other,
+}
+
+impl AISMessageBody {
+    /// Decode the message, the extended class B report carries a position and static data.
+    fn decode(m: &Message) -> serde_json::Result<Vec<Self>> {
+        let message_type = m["MessageType"].as_str().unwrap_or_default();
+        let body = m["Message"][message_type].clone();
+        Ok(match message_type {
+            "PositionReport" => vec![Self::PositionReport(serde_json::from_value(body)?)],
+            "StandardClassBPositionReport" => {
+                vec![Self::ClassBPositionReport(serde_json::from_value(body)?)]
+            }
+            "ExtendedClassBPositionReport" => {
+                let data: ExtendedClassBStaticData = serde_json::from_value(body.clone())?;
+                vec![
+                    Self::ClassBPositionReport(serde_json::from_value(body)?),
+                    Self::StaticDataReport(data.into()),
+                ]
+            }
+            "ShipStaticData" => vec![Self::ShipStaticData(serde_json::from_value(body)?)],
+            "StaticDataReport" => vec![Self::StaticDataReport(serde_json::from_value(body)?)],
+            "AidsToNavigationReport" => {
+                vec![Self::AidsToNavigationReport(serde_json::from_value(body)?)]
+            }
+            _ => vec![Self::Other],
+        })
+    }
+}
+
+#[derive(QueryData)]
+#[query_data(mutable)]
+pub(crate) struct VesselMut {
+    pub(crate) meta_data: &'static mut MetaData,
+    position_report: Option<&'static mut PositionReport>,
+    class_b_position_report: Option<&'static mut ClassBPositionReport>,
+    ship_static_data: Option<&'static mut ShipStaticData>,
+    static_data_report: Option<&'static mut StaticDataReport>,
+    aids_to_navigation_report: Option<&'static mut AidsToNavigationReport>,
+}
 
 fn handle_raw_packet(
     q_server: Query<(&ChannelId, &NetworkNode)>,
     mut commands: Commands,
-    mut q_vessels: Query<(&mut MetaData, )>,
-    mut mssi_index: ResMut<MSSIIndex>,
+    mut q_vessels: Query<VesselMut>,
+    mut mssi_index: ResMut<TrackIndex<VesselKey>>,
 ) {
     for (channel_id, net_node) in q_server.iter() {
         if *channel_id == AISSTREAM_CHANNEL {
             while let Ok(Some(packet)) = net_node.recv_message_channel.receiver.try_recv() {
-                let message: AuthMessage = serde_json::from_slice(&packet.bytes).unwrap();
-                // info!("Received: {:?}", message);
+                let message: AuthMessage = match serde_json::from_slice(&packet.bytes) {
+                    Ok(message) => message,
+                    Err(e) => {
+                        error!("invalid message: {:?}", e);
+                        continue;
+                    }
+                };
                 match message {
                     AuthMessage::AuthError(e) => {
                         error!("AuthError: {:?}", e.error);
                     }
                     AuthMessage::Message(m) => {
-                        // let position_report: PositionReport =
-                        //     serde_json::from_value(m["Message"]["PositionReport"].clone()).unwrap();
-                        // trace!("position_report: {:?}", position_report);
                         let meta_data: MetaData =
-                            serde_json::from_value(m["MetaData"].clone()).unwrap();
+                            match serde_json::from_value(m["MetaData"].clone()) {
+                                Ok(meta_data) => meta_data,
+                                Err(e) => {
+                                    error!("invalid MetaData of {}: {:?}", m["MessageType"], e);
+                                    continue;
+                                }
+                            };
                         trace!("meta_data: {:?}", meta_data);
-                        if let Some(entity) = mssi_index.get(&meta_data.mmsi) {
-                            if let Ok((mut meta_data_comp, )) =
-                                q_vessels.get_mut(*entity)
-                            {
-                                meta_data_comp.set_if_neq(meta_data);
-                                // position_report_comp.set_if_neq(position_report);
+                        let bodies = match AISMessageBody::decode(&m) {
+                            Ok(bodies) => bodies,
+                            Err(e) => {
+                                error!("invalid {}: {:?}", m["MessageType"], e);
+                                vec![AISMessageBody::Other]
                             }
-                        } else {
-                            let mssi = meta_data.mmsi;
-                            let entity = commands.spawn((meta_data, )).id();
-                            mssi_index.insert(mssi, entity);
+                        };
+                        for body in bodies {
+                            update_vessel(
+                                &mut commands,
+                                &mut q_vessels,
+                                &mut mssi_index,
+                                meta_data.clone(),
+                                body,
+                            );
                         }
                     }
                 }
@@ -138,54 +420,462 @@ fn handle_raw_packet(
     }
 }
 
+/// Apply one message to the vessel with the MMSI of `meta_data`, spawning it if it is new.
+pub(crate) fn update_vessel(
+    commands: &mut Commands,
+    q_vessels: &mut Query<VesselMut>,
+    mssi_index: &mut TrackIndex<VesselKey>,
+    meta_data: MetaData,
+    body: AISMessageBody,
+) {
+    let key = VesselKey(meta_data.mmsi);
+    let entity = match mssi_index.get(&key) {
+        Some(entity) => *entity,
+        None => {
+            let entity = commands.spawn(key.clone()).id();
+            mssi_index.insert(key, entity);
+            entity
+        }
+    };
+
+    match q_vessels.get_mut(entity) {
+        Ok(mut vessel) => {
+            vessel.meta_data.set_if_neq(meta_data);
+            match body {
+                AISMessageBody::PositionReport(report) => {
+                    upsert(commands, entity, vessel.position_report, report)
+                }
+                AISMessageBody::ClassBPositionReport(report) => {
+                    upsert(commands, entity, vessel.class_b_position_report, report)
+                }
+                AISMessageBody::ShipStaticData(data) => {
+                    upsert(commands, entity, vessel.ship_static_data, data)
+                }
+                AISMessageBody::StaticDataReport(report) => {
+                    let report = match vessel.static_data_report.as_ref() {
+                        Some(old) => old.merge(report),
+                        None => report,
+                    };
+                    upsert(commands, entity, vessel.static_data_report, report)
+                }
+                AISMessageBody::AidsToNavigationReport(report) => {
+                    upsert(commands, entity, vessel.aids_to_navigation_report, report)
+                }
+                AISMessageBody::Other => {}
+            }
+        }
+        // spawned in this frame
+        Err(_) => {
+            let mut entity_commands = commands.entity(entity);
+            entity_commands.insert(meta_data);
+            match body {
+                AISMessageBody::PositionReport(report) => {
+                    entity_commands.insert(report);
+                }
+                AISMessageBody::ClassBPositionReport(report) => {
+                    entity_commands.insert(report);
+                }
+                AISMessageBody::ShipStaticData(data) => {
+                    entity_commands.insert(data);
+                }
+                AISMessageBody::StaticDataReport(report) => {
+                    entity_commands.insert(report);
+                }
+                AISMessageBody::AidsToNavigationReport(report) => {
+                    entity_commands.insert(report);
+                }
+                AISMessageBody::Other => {}
+            }
+        }
+    }
+}
+
+/// update the component if it changed, or insert it if the vessel does not have one yet
+fn upsert<T: Component + PartialEq>(
+    commands: &mut Commands,
+    entity: Entity,
+    component: Option<Mut<T>>,
+    value: T,
+) {
+    match component {
+        Some(mut component) => {
+            component.set_if_neq(value);
+        }
+        None => {
+            commands.entity(entity).insert(value);
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Component, Reflect, PartialEq)]
-struct PositionReport {
+pub(crate) struct PositionReport {
     #[serde(rename = "MessageID")]
-    message_id: i32,
+    pub(crate) message_id: i32,
     #[serde(rename = "RepeatIndicator")]
-    repeat_indicator: i32,
+    pub(crate) repeat_indicator: i32,
     #[serde(rename = "UserID")]
-    user_id: i32,
+    pub(crate) user_id: i32,
     #[serde(rename = "Valid")]
-    valid: bool,
+    pub(crate) valid: bool,
     #[serde(rename = "NavigationalStatus")]
-    navigational_status: i32,
+    pub(crate) navigational_status: i32,
     #[serde(rename = "RateOfTurn")]
-    rate_of_turn: i32,
+    pub(crate) rate_of_turn: i32,
     #[serde(rename = "Sog")]
-    sog: f64,
+    pub(crate) sog: f64,
     #[serde(rename = "PositionAccuracy")]
-    position_accuracy: bool,
+    pub(crate) position_accuracy: bool,
     #[serde(rename = "Longitude")]
-    longitude: f64,
+    pub(crate) longitude: f64,
     #[serde(rename = "Latitude")]
-    latitude: f64,
+    pub(crate) latitude: f64,
     #[serde(rename = "Cog")]
-    cog: f64,
+    pub(crate) cog: f64,
     #[serde(rename = "TrueHeading")]
-    true_heading: i32,
+    pub(crate) true_heading: i32,
     #[serde(rename = "Timestamp")]
-    timestamp: i32,
+    pub(crate) timestamp: i32,
     #[serde(rename = "SpecialManoeuvreIndicator")]
-    special_manoeuvre_indicator: i32,
+    pub(crate) special_manoeuvre_indicator: i32,
     #[serde(rename = "Spare")]
-    spare: i32,
+    pub(crate) spare: i32,
     #[serde(rename = "Raim")]
-    raim: bool,
+    pub(crate) raim: bool,
     #[serde(rename = "CommunicationState")]
-    communication_state: i32,
+    pub(crate) communication_state: i32,
 }
 
+impl PositionReport {
+    fn heading(&self) -> Option<f64> {
+        heading(self.true_heading, self.cog)
+    }
+
+    fn speed(&self) -> Option<f64> {
+        speed(self.sog)
+    }
+}
+
+/// AIS messages 18 and 19, position reports of class B vessels (small craft, leisure boats)
 #[derive(Debug, Deserialize, Component, Reflect, PartialEq)]
-struct MetaData {
+pub(crate) struct ClassBPositionReport {
+    #[serde(rename = "MessageID")]
+    pub(crate) message_id: i32,
+    #[serde(rename = "RepeatIndicator")]
+    pub(crate) repeat_indicator: i32,
+    #[serde(rename = "UserID")]
+    pub(crate) user_id: i32,
+    #[serde(rename = "Valid")]
+    pub(crate) valid: bool,
+    #[serde(rename = "Sog")]
+    pub(crate) sog: f64,
+    #[serde(rename = "PositionAccuracy")]
+    pub(crate) position_accuracy: bool,
+    #[serde(rename = "Longitude")]
+    pub(crate) longitude: f64,
+    #[serde(rename = "Latitude")]
+    pub(crate) latitude: f64,
+    #[serde(rename = "Cog")]
+    pub(crate) cog: f64,
+    #[serde(rename = "TrueHeading")]
+    pub(crate) true_heading: i32,
+    #[serde(rename = "Timestamp")]
+    pub(crate) timestamp: i32,
+    #[serde(rename = "AssignedMode")]
+    pub(crate) assigned_mode: bool,
+    #[serde(rename = "Raim")]
+    pub(crate) raim: bool,
+}
+
+impl ClassBPositionReport {
+    fn heading(&self) -> Option<f64> {
+        heading(self.true_heading, self.cog)
+    }
+
+    fn speed(&self) -> Option<f64> {
+        speed(self.sog)
+    }
+}
+
+/// True heading in degrees, falls back to the course over ground if the heading is not
+/// available (511).
+fn heading(true_heading: i32, cog: f64) -> Option<f64> {
+    if true_heading != 511 {
+        Some(true_heading as f64)
+    } else if cog < 360.0 {
+        Some(cog)
+    } else {
+        None
+    }
+}
+
+/// Speed over ground in m/s, `None` if not available (102.3 knots).
+fn speed(sog: f64) -> Option<f64> {
+    if sog < 102.3 {
+        Some(sog * KNOT)
+    } else {
+        None
+    }
+}
+
+/// static part of the extended class B position report (message 19)
+#[derive(Debug, Deserialize)]
+struct ExtendedClassBStaticData {
+    #[serde(rename = "MessageID")]
+    message_id: i32,
+    #[serde(rename = "RepeatIndicator")]
+    repeat_indicator: i32,
+    #[serde(rename = "UserID")]
+    user_id: i32,
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "Type")]
+    ship_type: i32,
+    #[serde(rename = "Dimension")]
+    dimension: Dimension,
+    #[serde(rename = "FixType")]
+    fix_type: i32,
+}
+
+/// Stored like both parts of a message 24, which carries the same data except for the call
+/// sign. The empty call sign does not replace the one of an earlier part B, see
+/// [`StaticDataReport::merge`].
+impl From<ExtendedClassBStaticData> for StaticDataReport {
+    fn from(data: ExtendedClassBStaticData) -> Self {
+        Self {
+            message_id: data.message_id,
+            repeat_indicator: data.repeat_indicator,
+            user_id: data.user_id,
+            valid: true,
+            part_number: false,
+            report_a: StaticReportA {
+                valid: true,
+                name: data.name,
+            },
+            report_b: StaticReportB {
+                valid: true,
+                ship_type: data.ship_type,
+                call_sign: String::new(),
+                dimension: data.dimension,
+                fix_type: data.fix_type,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Component, Reflect, PartialEq, Clone)]
+pub(crate) struct MetaData {
     #[serde(rename = "MMSI")]
-    mmsi: i32,
+    pub(crate) mmsi: i32,
     #[serde(rename = "ShipName")]
-    ship_name: String,
-    longitude: f64,
-    latitude: f64,
+    pub(crate) ship_name: String,
+    pub(crate) longitude: f64,
+    pub(crate) latitude: f64,
     // #[serde(deserialize_with = "decode_time_utc")]
-    time_utc: String,
+    pub(crate) time_utc: String,
+}
+
+/// AIS message 5, static and voyage related data of class A vessels
+#[derive(Debug, Deserialize, Component, Reflect, PartialEq)]
+pub(crate) struct ShipStaticData {
+    #[serde(rename = "MessageID")]
+    pub(crate) message_id: i32,
+    #[serde(rename = "RepeatIndicator")]
+    pub(crate) repeat_indicator: i32,
+    #[serde(rename = "UserID")]
+    pub(crate) user_id: i32,
+    #[serde(rename = "Valid")]
+    pub(crate) valid: bool,
+    #[serde(rename = "AisVersion")]
+    pub(crate) ais_version: i32,
+    #[serde(rename = "ImoNumber")]
+    pub(crate) imo_number: i32,
+    #[serde(rename = "CallSign")]
+    pub(crate) call_sign: String,
+    #[serde(rename = "Name")]
+    pub(crate) name: String,
+    #[serde(rename = "Type")]
+    pub(crate) ship_type: i32,
+    #[serde(rename = "Dimension")]
+    pub(crate) dimension: Dimension,
+    #[serde(rename = "FixType")]
+    pub(crate) fix_type: i32,
+    #[serde(rename = "Eta")]
+    pub(crate) eta: Eta,
+    /// maximum present static draught in meters
+    #[serde(rename = "MaximumStaticDraught")]
+    pub(crate) maximum_static_draught: f64,
+    #[serde(rename = "Destination")]
+    pub(crate) destination: String,
+    #[serde(rename = "Dte")]
+    pub(crate) dte: bool,
+}
+
+/// Reference point of the reported position, distances in meters to bow (A), stern (B), port (C)
+/// and starboard (D).
+#[derive(Debug, Deserialize, Reflect, PartialEq, Clone, Default)]
+pub(crate) struct Dimension {
+    #[serde(rename = "A")]
+    pub(crate) a: i32,
+    #[serde(rename = "B")]
+    pub(crate) b: i32,
+    #[serde(rename = "C")]
+    pub(crate) c: i32,
+    #[serde(rename = "D")]
+    pub(crate) d: i32,
+}
+
+impl Dimension {
+    fn length(&self) -> Option<f64> {
+        (self.a + self.b > 0).then(|| (self.a + self.b) as f64)
+    }
+
+    fn width(&self) -> Option<f64> {
+        (self.c + self.d > 0).then(|| (self.c + self.d) as f64)
+    }
+}
+
+/// Estimated time of arrival in UTC, 0 means not available.
+#[derive(Debug, Deserialize, Reflect, PartialEq, Clone, Default)]
+pub(crate) struct Eta {
+    #[serde(rename = "Month")]
+    pub(crate) month: i32,
+    #[serde(rename = "Day")]
+    pub(crate) day: i32,
+    #[serde(rename = "Hour")]
+    pub(crate) hour: i32,
+    #[serde(rename = "Minute")]
+    pub(crate) minute: i32,
+}
+
+impl Eta {
+    fn to_text(&self) -> Option<String> {
+        if self.month == 0 || self.day == 0 || self.hour > 23 || self.minute > 59 {
+            return None;
+        }
+        Some(format!(
+            "{:02}-{:02} {:02}:{:02} UTC",
+            self.month, self.day, self.hour, self.minute
+        ))
+    }
+}
+
+/// AIS message 24, static data of class B vessels, sent in two parts
+#[derive(Debug, Deserialize, Component, Reflect, PartialEq, Clone)]
+pub(crate) struct StaticDataReport {
+    #[serde(rename = "MessageID")]
+    pub(crate) message_id: i32,
+    #[serde(rename = "RepeatIndicator")]
+    pub(crate) repeat_indicator: i32,
+    #[serde(rename = "UserID")]
+    pub(crate) user_id: i32,
+    #[serde(rename = "Valid")]
+    pub(crate) valid: bool,
+    #[serde(rename = "PartNumber")]
+    pub(crate) part_number: bool,
+    #[serde(rename = "ReportA")]
+    pub(crate) report_a: StaticReportA,
+    #[serde(rename = "ReportB")]
+    pub(crate) report_b: StaticReportB,
+}
+
+impl StaticDataReport {
+    /// keep the part of `self` the new report does not carry
+    pub(crate) fn merge(&self, mut new: StaticDataReport) -> StaticDataReport {
+        if !new.report_a.valid {
+            new.report_a = self.report_a.clone();
+        }
+        if !new.report_b.valid {
+            new.report_b = self.report_b.clone();
+        } else if ais_text(&new.report_b.call_sign).is_none() {
+            // message 19 has no call sign field
+            new.report_b.call_sign = self.report_b.call_sign.clone();
+        }
+        new
+    }
+}
+
+#[derive(Debug, Deserialize, Reflect, PartialEq, Clone, Default)]
+pub(crate) struct StaticReportA {
+    #[serde(rename = "Valid")]
+    pub(crate) valid: bool,
+    #[serde(rename = "Name")]
+    pub(crate) name: String,
+}
+
+#[derive(Debug, Deserialize, Reflect, PartialEq, Clone, Default)]
+pub(crate) struct StaticReportB {
+    #[serde(rename = "Valid")]
+    pub(crate) valid: bool,
+    #[serde(rename = "ShipType")]
+    pub(crate) ship_type: i32,
+    #[serde(rename = "CallSign")]
+    pub(crate) call_sign: String,
+    #[serde(rename = "Dimension")]
+    pub(crate) dimension: Dimension,
+    #[serde(rename = "FixType")]
+    pub(crate) fix_type: i32,
+}
+
+/// AIS message 21, buoys, lighthouses and other aids to navigation, virtual ones only exist as
+/// this message
+#[derive(Debug, Deserialize, Component, Reflect, PartialEq)]
+pub(crate) struct AidsToNavigationReport {
+    #[serde(rename = "MessageID")]
+    pub(crate) message_id: i32,
+    #[serde(rename = "RepeatIndicator")]
+    pub(crate) repeat_indicator: i32,
+    #[serde(rename = "UserID")]
+    pub(crate) user_id: i32,
+    #[serde(rename = "Valid")]
+    pub(crate) valid: bool,
+    /// kind of aid, 0 not specified .. 31 light vessel
+    #[serde(rename = "Type")]
+    pub(crate) aton_type: i32,
+    #[serde(rename = "Name")]
+    pub(crate) name: String,
+    #[serde(rename = "PositionAccuracy")]
+    pub(crate) position_accuracy: bool,
+    #[serde(rename = "Longitude")]
+    pub(crate) longitude: f64,
+    #[serde(rename = "Latitude")]
+    pub(crate) latitude: f64,
+    #[serde(rename = "Dimension")]
+    pub(crate) dimension: Dimension,
+    #[serde(rename = "Timestamp")]
+    pub(crate) timestamp: i32,
+    /// floating aid is off its assigned position
+    #[serde(rename = "OffPosition")]
+    pub(crate) off_position: bool,
+    #[serde(rename = "Raim")]
+    pub(crate) raim: bool,
+    #[serde(rename = "VirtualAtoN")]
+    pub(crate) virtual_aton: bool,
+    #[serde(rename = "AssignedMode")]
+    pub(crate) assigned_mode: bool,
+    /// up to 14 more characters of the name
+    #[serde(rename = "NameExtension", default)]
+    pub(crate) name_extension: String,
+}
+
+impl AidsToNavigationReport {
+    fn full_name(&self) -> Option<String> {
+        ais_text(&format!(
+            "{}{}",
+            self.name.trim_end_matches('@'),
+            self.name_extension
+        ))
+    }
+
+    /// The off position flag is only valid with a time stamp of 0 to 59 seconds.
+    fn is_off_position(&self) -> bool {
+        self.off_position && self.timestamp < 60
+    }
+}
+
+/// AIS text fields are padded with `@` and spaces
+pub(crate) fn ais_text(text: &str) -> Option<String> {
+    let text = text.trim_end_matches('@').trim();
+    (!text.is_empty()).then(|| text.to_string())
 }
 
 #[allow(dead_code)]
@@ -200,61 +890,512 @@ where
     Ok(naive_dt)
 }
 
-fn watch_added(query: Query<(Entity, &MetaData), (Added<MetaData>,)>, mut commands: Commands) {
-    for (e, meta_data) in query.iter() {
-        trace!("Added: {} {}", meta_data.mmsi, meta_data.ship_name);
-        let coord = to_coords(meta_data);
-        let props = to_props(meta_data);
+#[derive(QueryData)]
+struct Vessel {
+    meta_data: &'static MetaData,
+    position_report: Option<&'static PositionReport>,
+    class_b_position_report: Option<&'static ClassBPositionReport>,
+    ship_static_data: Option<&'static ShipStaticData>,
+    static_data_report: Option<&'static StaticDataReport>,
+}
 
-        commands.entity(e).insert((
-            coord,
-            PropertyList(props),
-            ObjectNeedSync::Spawn,
-            ActiveState::always(),
-        ));
+impl VesselItem<'_> {
+    fn heading(&self) -> Option<f64> {
+        self.position_report
+            .and_then(PositionReport::heading)
+            .or_else(|| {
+                self.class_b_position_report
+                    .and_then(ClassBPositionReport::heading)
+            })
+    }
+
+    fn speed(&self) -> Option<f64> {
+        self.position_report
+            .and_then(PositionReport::speed)
+            .or_else(|| {
+                self.class_b_position_report
+                    .and_then(ClassBPositionReport::speed)
+            })
     }
 }
 
-fn watch_changed(
-    mut query: Query<
-        (
-            Entity,
-            &MetaData,
-            &mut Coords,
-            &mut PropertyList,
-            &mut ActiveState,
-        ),
-        Changed<StateVector>,
-    >,
-    mut commands: Commands,
-) {
-    for (entity, meta_data, mut coords, mut props_list, mut active_state) in query.iter_mut() {
-        coords.set_if_neq(to_coords(&meta_data));
-        props_list.set_if_neq(PropertyList(to_props(&meta_data)));
-        active_state.toggle();
-        commands.entity(entity).insert(ObjectNeedSync::Update);
+/// Vessels stay until they are despawned, position reports and static data arrive in separate
+/// messages so any of them may be the one that changed.
+impl TrackSource for Vessel {
+    type Key = VesselKey;
+    type Data = Vessel;
+    type Changed = (
+        Or<(
+            Changed<MetaData>,
+            Changed<PositionReport>,
+            Changed<ClassBPositionReport>,
+            Changed<ShipStaticData>,
+            Changed<StaticDataReport>,
+        )>,
+        Without<AidsToNavigationReport>,
+    );
+
+    const TIMEOUT: Option<Duration> = None;
+
+    fn coords(vessel: &VesselItem) -> Coords {
+        let heading = vessel.heading();
+        Coords {
+            longitude: Some(vessel.meta_data.longitude),
+            latitude: Some(vessel.meta_data.latitude),
+            altitude: Some(0.0),
+            u: None,
+            v: None,
+            roll: None,
+            pitch: None,
+            yaw: heading,
+            heading,
+        }
+    }
+
+    fn props(vessel: &VesselItem) -> Vec<Property> {
+        let mut list = vec![Property::Type(HashSet::from_iter([Tag::Watercraft]))];
+
+        // local receivers only learn the name from the static data messages
+        if let Some(ship_name) = ais_text(&vessel.meta_data.ship_name) {
+            list.push(Property::CallSign(ship_name));
+        }
+
+        if let Some(speed) = vessel.speed() {
+            // Tacview has no ground speed property, TAS would be off by the wind or current
+            list.push(Property::Unknown(
+                "GroundSpeed".to_string(),
+                format!("{:.1}", speed),
+            ));
+        }
+        if vessel.class_b_position_report.is_some() {
+            list.push(Property::Unknown("AISClass".to_string(), "B".to_string()));
+        }
+
+        let mut dimension = None;
+        if let Some(data) = vessel.ship_static_data {
+            if let Some(call_sign) = ais_text(&data.call_sign) {
+                list.push(Property::Registration(call_sign));
+            }
+            if data.imo_number > 0 {
+                list.push(Property::Unknown(
+                    "IMO".to_string(),
+                    data.imo_number.to_string(),
+                ));
+            }
+            if let Some(destination) = ais_text(&data.destination) {
+                list.push(Property::Unknown("Destination".to_string(), destination));
+            }
+            if let Some(eta) = data.eta.to_text() {
+                list.push(Property::Unknown("ETA".to_string(), eta));
+            }
+            if data.maximum_static_draught > 0.0 {
+                list.push(Property::Unknown(
+                    "Draught".to_string(),
+                    data.maximum_static_draught.to_string(),
+                ));
+            }
+            dimension = Some(&data.dimension);
+        } else if let Some(report) = vessel.static_data_report {
+            if report.report_b.valid {
+                if let Some(call_sign) = ais_text(&report.report_b.call_sign) {
+                    list.push(Property::Registration(call_sign));
+                }
+                dimension = Some(&report.report_b.dimension);
+            }
+        }
+
+        if let Some(dimension) = dimension {
+            if let Some(length) = dimension.length() {
+                list.push(Property::Length(length));
+            }
+            if let Some(width) = dimension.width() {
+                list.push(Property::Width(width));
+            }
+        }
+
+        list
+    }
+}
+
+#[derive(QueryData)]
+struct AidToNavigation {
+    meta_data: &'static MetaData,
+    report: &'static AidsToNavigationReport,
+}
+
+/// Aids to navigation do not move, they stay until despawned.
+impl TrackSource for AidToNavigation {
+    type Key = VesselKey;
+    type Data = AidToNavigation;
+    type Changed = Or<(Changed<MetaData>, Changed<AidsToNavigationReport>)>;
+
+    const TIMEOUT: Option<Duration> = None;
+
+    fn coords(aid: &AidToNavigationItem) -> Coords {
+        Coords {
+            longitude: Some(aid.meta_data.longitude),
+            latitude: Some(aid.meta_data.latitude),
+            altitude: Some(0.0),
+            u: None,
+            v: None,
+            roll: None,
+            pitch: None,
+            yaw: None,
+            heading: None,
+        }
+    }
+
+    fn props(aid: &AidToNavigationItem) -> Vec<Property> {
+        let report = aid.report;
+        let mut list = vec![
+            Property::Type(HashSet::from_iter([
+                Tag::Navaid,
+                Tag::Static,
+                Tag::Waypoint,
+            ])),
+            Property::Unknown(
+                "AtoNType".to_string(),
+                aton_type_name(report.aton_type).to_string(),
+            ),
+        ];
+
+        if let Some(name) = report
+            .full_name()
+            .or_else(|| ais_text(&aid.meta_data.ship_name))
+        {
+            list.push(Property::Name(name));
+        }
+        if report.is_off_position() {
+            list.push(Property::Unknown(
+                "OffPosition".to_string(),
+                "1".to_string(),
+            ));
+        }
+        if report.virtual_aton {
+            list.push(Property::Unknown(
+                "VirtualAtoN".to_string(),
+                "1".to_string(),
+            ));
+        }
+        if let Some(length) = report.dimension.length() {
+            list.push(Property::Length(length));
+        }
+        if let Some(width) = report.dimension.width() {
+            list.push(Property::Width(width));
+        }
+
+        list
     }
 }
 
-fn to_coords(position_report: &MetaData) -> Coords {
-    Coords {
-        longitude: Some(position_report.longitude),
-        latitude: Some(position_report.latitude),
-        altitude: Some(0.0),
-        u: None,
-        v: None,
-        roll: None,
-        pitch: None,
-        yaw: None,
-        heading: None,
+/// Kind of aid to navigation as listed in ITU-R M.1371.
+fn aton_type_name(aton_type: i32) -> &'static str {
+    match aton_type {
+        1 => "Reference Point",
+        2 => "RACON",
+        3 => "Fixed Structure",
+        5 => "Light",
+        6 => "Light with Sectors",
+        7 => "Leading Light Front",
+        8 => "Leading Light Rear",
+        9 => "Beacon Cardinal N",
+        10 => "Beacon Cardinal E",
+        11 => "Beacon Cardinal S",
+        12 => "Beacon Cardinal W",
+        13 => "Beacon Port Hand",
+        14 => "Beacon Starboard Hand",
+        15 => "Beacon Preferred Channel Port Hand",
+        16 => "Beacon Preferred Channel Starboard Hand",
+        17 => "Beacon Isolated Danger",
+        18 => "Beacon Safe Water",
+        19 => "Beacon Special Mark",
+        20 => "Cardinal Mark N",
+        21 => "Cardinal Mark E",
+        22 => "Cardinal Mark S",
+        23 => "Cardinal Mark W",
+        24 => "Port Hand Mark",
+        25 => "Starboard Hand Mark",
+        26 => "Preferred Channel Port Hand",
+        27 => "Preferred Channel Starboard Hand",
+        28 => "Isolated Danger",
+        29 => "Safe Water",
+        30 => "Special Mark",
+        31 => "Light Vessel",
+        _ => "Unspecified",
     }
 }
 
-fn to_props(meta_data: &MetaData) -> Vec<Property> {
-    let list = vec![
-        Property::CallSign(meta_data.ship_name.clone()),
-        Property::Type(HashSet::from_iter([Tag::Watercraft])),
-    ];
+#[cfg(test)]
+mod tests {
+    use bevy_tacview::systems::ObjectNeedSync;
+
+    use super::*;
+
+    fn decode(json: &str) -> Vec<AISMessageBody> {
+        AISMessageBody::decode(&serde_json::from_str(json).unwrap()).unwrap()
+    }
+
+    fn static_data_report(bodies: Vec<AISMessageBody>) -> StaticDataReport {
+        bodies
+            .into_iter()
+            .find_map(|body| match body {
+                AISMessageBody::StaticDataReport(report) => Some(report),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn extended_class_b_keeps_call_sign() {
+        let part_b = static_data_report(decode(
+            r#"{
+                "MessageType": "StaticDataReport",
+                "Message": {"StaticDataReport": {
+                    "MessageID": 24, "RepeatIndicator": 0, "UserID": 211234560, "Valid": true,
+                    "PartNumber": true,
+                    "ReportA": {"Valid": false, "Name": ""},
+                    "ReportB": {"Valid": true, "ShipType": 37, "CallSign": "DA1234",
+                        "Dimension": {"A": 5, "B": 5, "C": 2, "D": 2}, "FixType": 1}
+                }}
+            }"#,
+        ));
+        let extended = static_data_report(decode(
+            r#"{
+                "MessageType": "ExtendedClassBPositionReport",
+                "Message": {"ExtendedClassBPositionReport": {
+                    "MessageID": 19, "RepeatIndicator": 0, "UserID": 211234560, "Valid": true,
+                    "Spare1": 0, "Sog": 5.2, "PositionAccuracy": true,
+                    "Longitude": 9.9, "Latitude": 53.5, "Cog": 90.0, "TrueHeading": 91,
+                    "Timestamp": 30, "Spare2": 0, "Name": "SEA BREEZE@@@@@@@@@@", "Type": 37,
+                    "Dimension": {"A": 5, "B": 5, "C": 2, "D": 2}, "FixType": 1,
+                    "Raim": false, "Dte": false, "AssignedMode": false, "Spare3": 0
+                }}
+            }"#,
+        ));
+
+        let merged = part_b.merge(extended);
+        assert_eq!(merged.report_a.name, "SEA BREEZE@@@@@@@@@@");
+        assert_eq!(merged.report_b.call_sign, "DA1234");
+    }
+
+    /// messages handed to `update_vessel` on the next update, like `handle_raw_packet` does
+    #[derive(Resource, Default)]
+    struct Inbox(Vec<(MetaData, AISMessageBody)>);
+
+    fn deliver(
+        mut inbox: ResMut<Inbox>,
+        mut commands: Commands,
+        mut q_vessels: Query<VesselMut>,
+        mut mssi_index: ResMut<TrackIndex<VesselKey>>,
+    ) {
+        for (meta_data, body) in inbox.0.drain(..) {
+            update_vessel(
+                &mut commands,
+                &mut q_vessels,
+                &mut mssi_index,
+                meta_data,
+                body,
+            );
+        }
+    }
+
+    fn position_report(mmsi: i32, longitude: f64, latitude: f64) -> (MetaData, AISMessageBody) {
+        let meta_data = MetaData {
+            mmsi,
+            ship_name: "SEA BREEZE".to_string(),
+            longitude,
+            latitude,
+            time_utc: "2024-06-01 12:00:00.000000000 +0000 UTC".to_string(),
+        };
+        let body = decode(&format!(
+            r#"{{
+                "MessageType": "PositionReport",
+                "Message": {{"PositionReport": {{
+                    "MessageID": 1, "RepeatIndicator": 0, "UserID": {mmsi}, "Valid": true,
+                    "NavigationalStatus": 0, "RateOfTurn": 0, "Sog": 10.2,
+                    "PositionAccuracy": true, "Longitude": {longitude}, "Latitude": {latitude},
+                    "Cog": 45.0, "TrueHeading": 44, "Timestamp": 30,
+                    "SpecialManoeuvreIndicator": 0, "Spare": 0, "Raim": false,
+                    "CommunicationState": 0
+                }}}}
+            }}"#
+        ))
+        .pop()
+        .unwrap();
+        (meta_data, body)
+    }
+
+    #[test]
+    fn position_reports_update_the_vessel() {
+        let mut app = App::new();
+        app.add_plugins(VesselPlugin)
+            .init_resource::<Inbox>()
+            .add_systems(Update, deliver);
+
+        app.world
+            .resource_mut::<Inbox>()
+            .0
+            .push(position_report(211234560, 9.9, 53.5));
+        app.update();
+        app.update();
+
+        let entity = *app
+            .world
+            .resource::<TrackIndex<VesselKey>>()
+            .get(&VesselKey(211234560))
+            .unwrap();
+        assert!(matches!(
+            app.world.get::<ObjectNeedSync>(entity),
+            Some(ObjectNeedSync::Spawn)
+        ));
+        let coords = app.world.get::<Coords>(entity).unwrap();
+        assert_eq!(coords.longitude, Some(9.9));
+        assert_eq!(coords.latitude, Some(53.5));
+        // written to Tacview
+        app.world.entity_mut(entity).remove::<ObjectNeedSync>();
+
+        app.world
+            .resource_mut::<Inbox>()
+            .0
+            .push(position_report(211234560, 9.91, 53.51));
+        app.update();
+        app.update();
+
+        assert_eq!(app.world.resource::<TrackIndex<VesselKey>>().len(), 1);
+        let coords = app.world.get::<Coords>(entity).unwrap();
+        assert_eq!(coords.longitude, Some(9.91));
+        assert_eq!(coords.latitude, Some(53.51));
+        assert!(matches!(
+            app.world.get::<ObjectNeedSync>(entity),
+            Some(ObjectNeedSync::Update)
+        ));
+    }
+
+    fn node_event(app: &mut App, node: Entity, event: NetworkEvent) {
+        app.world.send_event(NetworkNodeEvent {
+            node,
+            channel_id: AISSTREAM_CHANNEL,
+            event,
+        });
+    }
+
+    /// the retry was scheduled `delay` from now, give or take the time the test took since
+    fn assert_retry_in(connection: &AISStreamConnection, delay: Duration) {
+        let retry_in = connection.retry_at.unwrap() - Instant::now();
+        assert!(retry_in <= delay, "{retry_in:?} > {delay:?}");
+        assert!(
+            retry_in > delay - Duration::from_millis(200),
+            "{retry_in:?} < {delay:?}"
+        );
+    }
+
+    #[test]
+    fn reconnects_with_backoff() {
+        let mut app = App::new();
+        app.insert_resource(AISStreamResource {
+            api_key: "key".to_string(),
+            url: "wss://127.0.0.1:9/v0/stream".to_string(),
+        })
+        .init_resource::<Config>()
+        .insert_resource(AISStreamConnection {
+            jitter: Jitter::seeded(7),
+            ..default()
+        })
+        .add_event::<NetworkNodeEvent>()
+        .add_systems(Update, (handle_connect, reconnect).chain());
+        // the same delays as the connection will draw
+        let mut jitter = Jitter::seeded(7);
+        let mut delay = |backoff: Duration| backoff.mul_f64(0.5 + jitter.next() / 2.0);
+
+        // stands in for the websocket node, its events are what the connection state follows
+        let node = app.world.spawn(AISSTREAM_CHANNEL).id();
+        node_event(&mut app, node, NetworkEvent::Connected);
+        app.update();
+        assert_eq!(
+            app.world.resource::<AISStreamConnection>().state,
+            ConnectionState::Connected
+        );
+
+        // a failure reported twice schedules a single attempt
+        node_event(&mut app, node, NetworkEvent::Disconnected);
+        node_event(&mut app, node, NetworkEvent::Disconnected);
+        app.update();
+        let connection = app.world.resource::<AISStreamConnection>();
+        assert_eq!(connection.state, ConnectionState::Disconnected);
+        assert_eq!(connection.failures, 1);
+        assert_retry_in(connection, delay(RECONNECT_BASE));
+        assert!(app.world.get_entity(node).is_none());
+
+        // the backoff is not over yet
+        app.update();
+        assert_eq!(app.world.resource::<AISStreamConnection>().reconnects, 0);
 
-    list
+        app.world.resource_mut::<AISStreamConnection>().retry_at = Some(Instant::now());
+        app.update();
+        let connection = app.world.resource::<AISStreamConnection>();
+        assert_eq!(connection.state, ConnectionState::Connecting);
+        assert_eq!(connection.reconnects, 1);
+        let node = app
+            .world
+            .query_filtered::<Entity, With<ConnectTo>>()
+            .single(&app.world);
+
+        // the next failure waits twice as long before the jitter
+        node_event(&mut app, node, NetworkEvent::Disconnected);
+        app.update();
+        let connection = app.world.resource::<AISStreamConnection>();
+        assert_eq!(connection.failures, 2);
+        assert_retry_in(connection, delay(RECONNECT_BASE * 2));
+
+        app.world.resource_mut::<AISStreamConnection>().retry_at = Some(Instant::now());
+        app.update();
+        let node = app
+            .world
+            .query_filtered::<Entity, With<ConnectTo>>()
+            .single(&app.world);
+        node_event(&mut app, node, NetworkEvent::Connected);
+        app.update();
+        let connection = app.world.resource::<AISStreamConnection>();
+        assert_eq!(connection.state, ConnectionState::Connected);
+        assert_eq!(connection.failures, 0);
+        assert_eq!(connection.reconnects, 2);
+    }
+
+    #[test]
+    fn reconnects_through_stand_in() {
+        let (url, subscriptions) = crate::network::mock::websocket_server();
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(bevy_octopus::plugin::OctopusPlugin)
+            .insert_resource(AISStreamResource {
+                api_key: "key".to_string(),
+                url,
+            })
+            .init_resource::<Config>()
+            .insert_resource(AISStreamConnection {
+                jitter: Jitter::seeded(7),
+                ..default()
+            })
+            .add_plugins(AISStreamPlugin);
+
+        // the stand-in drops every connection after its subscription
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut received = Vec::new();
+        while received.len() < 2 && Instant::now() < deadline {
+            app.update();
+            received.extend(subscriptions.try_iter());
+            // skip the backoff
+            let mut connection = app.world.resource_mut::<AISStreamConnection>();
+            if connection.retry_at.is_some() {
+                connection.retry_at = Some(Instant::now());
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(received.len(), 2, "{received:?}");
+        assert!(received[0].contains(r#""APIKey":"key""#), "{}", received[0]);
+        assert_eq!(received[0], received[1]);
+        let connection = app.world.resource::<AISStreamConnection>();
+        assert!(connection.reconnects >= 1);
+    }
 }
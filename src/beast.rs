@@ -0,0 +1,289 @@
+use std::time::Duration;
+
+use crate::adsb::{AdsbAircraft, AdsbMessage, AdsbPlugin};
+use crate::config::RawFormat;
+use crate::modes::{ModeSDecoder, ModeSKind, ModeSMessage};
+use crate::network::{log_connection, split_lines};
+use crate::track::Tracks;
+use bevy::prelude::*;
+use bevy::time::common_conditions::on_real_timer;
+use bevy_octopus::prelude::*;
+
+const BEAST_CHANNEL: ChannelId = ChannelId("BEAST");
+
+/// escape byte starting every Beast frame
+const BEAST_ESCAPE: u8 = 0x1a;
+
+/// Decodes raw Mode-S replies of a local receiver, in Beast binary format (port 30005) or AVR
+/// hex lines (port 30002).
+pub struct BeastPlugin {
+    /// address of the feed, e.g. tcp://127.0.0.1:30005
+    pub address: String,
+    pub format: RawFormat,
+}
+
+impl Plugin for BeastPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<AdsbPlugin>() {
+            app.add_plugins(AdsbPlugin);
+        }
+        app.insert_resource(BeastResource {
+            address: self.address.clone(),
+            format: self.format,
+        })
+        .init_resource::<BeastDecoder>()
+        .add_systems(Startup, setup)
+        .add_systems(
+            Update,
+            (
+                log_connection(BEAST_CHANNEL),
+                handle_raw_packet,
+                prune_decoder.run_if(on_real_timer(Duration::from_secs(60))),
+            ),
+        );
+    }
+}
+
+#[derive(Resource)]
+pub struct BeastResource {
+    pub address: String,
+    pub format: RawFormat,
+}
+
+/// Mode-S decoder and the bytes of a frame that is not complete yet.
+#[derive(Resource, Default)]
+struct BeastDecoder {
+    decoder: ModeSDecoder,
+    buffer: Vec<u8>,
+}
+
+/// The fields of an aircraft one decoded reply carries.
+fn adsb_message(ModeSMessage { icao24, kind }: ModeSMessage) -> AdsbMessage {
+    let mut message = AdsbMessage {
+        icao24: format!("{:06x}", icao24),
+        ..default()
+    };
+    match kind {
+        ModeSKind::Identification { callsign, category } => {
+            message.callsign = (!callsign.is_empty()).then_some(callsign);
+            message.category = Some(category);
+        }
+        ModeSKind::AirbornePosition {
+            position,
+            baro_altitude,
+            geo_altitude,
+        } => {
+            message.on_ground = Some(false);
+            if let Some((latitude, longitude)) = position {
+                message.latitude = Some(latitude);
+                message.longitude = Some(longitude);
+            }
+            message.baro_altitude = baro_altitude;
+            message.geo_altitude = geo_altitude;
+        }
+        ModeSKind::SurfacePosition => {
+            message.on_ground = Some(true);
+        }
+        ModeSKind::Velocity {
+            velocity,
+            track,
+            vertical_rate,
+        } => {
+            message.ground_speed = velocity;
+            message.track = track;
+            message.vertical_rate = vertical_rate;
+        }
+        ModeSKind::Squawk(squawk) => {
+            message.squawk = Some(squawk);
+        }
+    }
+    message
+}
+
+/// setup the connection to the receiver
+fn setup(res: Res<BeastResource>, mut commands: Commands) {
+    commands.spawn((BEAST_CHANNEL, ConnectTo::new(&res.address)));
+}
+
+fn handle_raw_packet(
+    q_server: Query<(&ChannelId, &NetworkNode)>,
+    mut tracks: Tracks<AdsbAircraft>,
+    mut beast: ResMut<BeastDecoder>,
+    res: Res<BeastResource>,
+    time: Res<Time<Real>>,
+) {
+    let now = time.elapsed_seconds_f64();
+    let BeastDecoder { decoder, buffer } = &mut *beast;
+    let mut messages = vec![];
+    for (channel_id, net_node) in q_server.iter() {
+        if *channel_id != BEAST_CHANNEL {
+            continue;
+        }
+        while let Ok(Some(packet)) = net_node.recv_message_channel.receiver.try_recv() {
+            let frames = match res.format {
+                RawFormat::Beast => {
+                    buffer.extend_from_slice(&packet.bytes);
+                    split_beast_frames(buffer)
+                }
+                RawFormat::Avr => split_lines(buffer, &packet.bytes)
+                    .iter()
+                    .filter_map(|line| avr_frame(line))
+                    .collect(),
+            };
+            messages.extend(frames.iter().filter_map(|frame| decoder.decode(frame, now)));
+        }
+    }
+
+    tracks.update(messages.into_iter().map(|message| {
+        trace!("mode-s {:06x}: {:?}", message.icao24, message.kind);
+        adsb_message(message)
+    }));
+}
+
+/// Take the complete Beast frames off the front of `buffer` and return their Mode-S payloads,
+/// Mode-A/C and status frames are skipped.
+fn split_beast_frames(buffer: &mut Vec<u8>) -> Vec<Vec<u8>> {
+    let mut frames = vec![];
+    let mut start = 0;
+    'frames: loop {
+        let Some(offset) = buffer[start..].iter().position(|b| *b == BEAST_ESCAPE) else {
+            // no frame start left, drop the garbage
+            start = buffer.len();
+            break;
+        };
+        start += offset;
+        let Some(frame_type) = buffer.get(start + 1) else {
+            break;
+        };
+        let len = match frame_type {
+            b'1' => 2,
+            b'2' => 7,
+            b'3' => 14,
+            _ => {
+                // status frames or not a frame start at all, resync on the next escape
+                start += 1;
+                continue;
+            }
+        };
+        // 6 bytes timestamp, 1 byte signal level, then the payload
+        let mut frame = Vec::with_capacity(7 + len);
+        let mut i = start + 2;
+        while frame.len() < 7 + len {
+            let Some(byte) = buffer.get(i) else {
+                break 'frames;
+            };
+            if *byte == BEAST_ESCAPE {
+                match buffer.get(i + 1) {
+                    Some(&BEAST_ESCAPE) => i += 1,
+                    // a new frame starts inside this one, drop the broken frame
+                    Some(_) => {
+                        start = i;
+                        continue 'frames;
+                    }
+                    None => break 'frames,
+                }
+            }
+            frame.push(buffer[i]);
+            i += 1;
+        }
+        if *frame_type == b'2' || *frame_type == b'3' {
+            frames.push(frame.split_off(7));
+        }
+        start = i;
+    }
+    buffer.drain(..start);
+    frames
+}
+
+/// Payload of an AVR line (`*8d4840d6202cc371c32ce0576098;`).
+fn avr_frame(line: &str) -> Option<Vec<u8>> {
+    let line = line.trim().trim_end_matches(';');
+    let hex = if let Some(hex) = line.strip_prefix('*') {
+        hex
+    } else if let Some(hex) = line.strip_prefix('@') {
+        // 12 hex digits MLAT timestamp before the payload
+        hex.get(12..)?
+    } else {
+        return None;
+    };
+    decode_hex(hex)
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn prune_decoder(mut beast: ResMut<BeastDecoder>, time: Res<Time<Real>>) {
+    beast.decoder.prune(time.elapsed_seconds_f64(), 60.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAYLOAD: &str = "8d4840d6202cc371c32ce0576098";
+
+    /// Beast Mode-S long frame
+    fn long_frame() -> Vec<u8> {
+        let mut frame = vec![BEAST_ESCAPE, b'3'];
+        // timestamp with the escaped 0x1a, signal level
+        frame.extend([0, 0, BEAST_ESCAPE, BEAST_ESCAPE, 0, 0, 0, 0x80]);
+        frame.extend(decode_hex(PAYLOAD).unwrap());
+        frame
+    }
+
+    #[test]
+    fn unescapes_frame() {
+        let mut buffer = long_frame();
+        assert_eq!(
+            split_beast_frames(&mut buffer),
+            vec![decode_hex(PAYLOAD).unwrap()]
+        );
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn keeps_incomplete_frame() {
+        let frame = long_frame();
+        let mut buffer = frame[..12].to_vec();
+        assert!(split_beast_frames(&mut buffer).is_empty());
+        assert_eq!(buffer, frame[..12]);
+        buffer.extend_from_slice(&frame[12..]);
+        assert_eq!(split_beast_frames(&mut buffer).len(), 1);
+    }
+
+    #[test]
+    fn resyncs_after_garbage() {
+        // stray bytes, an escape that starts no frame, a status frame and a frame cut short by
+        // the next one
+        let mut buffer = vec![0x42, 0x00, BEAST_ESCAPE, b'x', 0x42];
+        buffer.extend([BEAST_ESCAPE, b'4', 0, 0, 0]);
+        buffer.extend(&long_frame()[..8]);
+        buffer.extend(long_frame());
+        assert_eq!(
+            split_beast_frames(&mut buffer),
+            vec![decode_hex(PAYLOAD).unwrap()]
+        );
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn avr_lines() {
+        let payload = decode_hex(PAYLOAD).unwrap();
+        assert_eq!(
+            avr_frame("*8d4840d6202cc371c32ce0576098;\r"),
+            Some(payload.clone())
+        );
+        assert_eq!(
+            avr_frame("@0000a1b2c3d48d4840d6202cc371c32ce0576098;"),
+            Some(payload)
+        );
+        assert_eq!(avr_frame("garbage"), None);
+    }
+}
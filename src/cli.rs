@@ -0,0 +1,151 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::config::{Config, DEFAULT_CONFIG_PATH};
+
+/// Command line options, every option given here overrides the config file.
+#[derive(Parser, Debug)]
+#[command(version, about = "Tacview real-time telemetry host for live air and sea traffic")]
+pub struct Cli {
+    /// Path of the TOML config file.
+    #[arg(long, default_value = DEFAULT_CONFIG_PATH)]
+    pub config: PathBuf,
+    /// Address the Tacview listener binds to, e.g. tcp://0.0.0.0:42674
+    #[arg(long)]
+    pub listen: Option<String>,
+    /// Run without window, renderer and inspector UI.
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub headless: Option<bool>,
+    /// Poll aircraft states from OpenSky.
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub enable_opensky: Option<bool>,
+    /// Subscribe to vessel positions from AISStream.
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub enable_ais: Option<bool>,
+    /// Websocket endpoint of AISStream, e.g. ws://127.0.0.1:8080 for a local stand-in
+    #[arg(long)]
+    pub ais_url: Option<String>,
+    /// Read a local SBS-1 BaseStation feed.
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub enable_sbs: Option<bool>,
+    /// Address of the SBS-1 BaseStation feed, e.g. tcp://127.0.0.1:30003
+    #[arg(long)]
+    pub sbs_address: Option<String>,
+    /// Decode a local raw Mode-S feed (Beast or AVR).
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub enable_beast: Option<bool>,
+    /// Address of the raw Mode-S feed, e.g. tcp://127.0.0.1:30005
+    #[arg(long)]
+    pub beast_address: Option<String>,
+    /// Decode AIVDM sentences of a local AIS receiver.
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub enable_nmea: Option<bool>,
+    /// Address of the AIS receiver, e.g. udp://0.0.0.0:10110 or tcp://127.0.0.1:10110
+    #[arg(long)]
+    pub nmea_address: Option<String>,
+    /// Poll the aircraft.json of a readsb / tar1090 instance.
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub enable_readsb: Option<bool>,
+    /// URL of aircraft.json, e.g. http://127.0.0.1/tar1090/data/aircraft.json
+    #[arg(long)]
+    pub readsb_url: Option<String>,
+    /// Read OGN / FLARM beacons from an APRS-IS server.
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub enable_ogn: Option<bool>,
+    /// Address of the APRS-IS server, e.g. tcp://aprs.glidernet.org:14580
+    #[arg(long)]
+    pub ogn_address: Option<String>,
+    /// Listen for MAVLink telemetry of our own vehicles.
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub enable_mavlink: Option<bool>,
+    /// UDP address to listen on for MAVLink, e.g. udp://0.0.0.0:14550
+    #[arg(long)]
+    pub mavlink_address: Option<String>,
+    /// Seconds between two OpenSky state requests.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub opensky_interval: Option<u64>,
+}
+
+impl Cli {
+    /// Override the values of `config` with the options given on the command line.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(listen) = self.listen.as_ref() {
+            config.listen = listen.clone();
+        }
+        if let Some(headless) = self.headless {
+            config.headless = headless;
+        }
+        if let Some(enabled) = self.enable_opensky {
+            config.opensky.enabled = enabled;
+        }
+        if let Some(enabled) = self.enable_ais {
+            config.aisstream.enabled = enabled;
+        }
+        if let Some(url) = self.ais_url.as_ref() {
+            config.aisstream.url = url.clone();
+        }
+        if let Some(enabled) = self.enable_sbs {
+            config.sbs.enabled = enabled;
+        }
+        if let Some(address) = self.sbs_address.as_ref() {
+            config.sbs.address = address.clone();
+        }
+        if let Some(enabled) = self.enable_beast {
+            config.beast.enabled = enabled;
+        }
+        if let Some(address) = self.beast_address.as_ref() {
+            config.beast.address = address.clone();
+        }
+        if let Some(enabled) = self.enable_nmea {
+            config.nmea.enabled = enabled;
+        }
+        if let Some(address) = self.nmea_address.as_ref() {
+            config.nmea.address = address.clone();
+        }
+        if let Some(enabled) = self.enable_readsb {
+            config.readsb.enabled = enabled;
+        }
+        if let Some(url) = self.readsb_url.as_ref() {
+            config.readsb.url = url.clone();
+        }
+        if let Some(enabled) = self.enable_ogn {
+            config.ogn.enabled = enabled;
+        }
+        if let Some(address) = self.ogn_address.as_ref() {
+            config.ogn.address = address.clone();
+        }
+        if let Some(enabled) = self.enable_mavlink {
+            config.mavlink.enabled = enabled;
+        }
+        if let Some(address) = self.mavlink_address.as_ref() {
+            config.mavlink.address = address.clone();
+        }
+        if let Some(interval) = self.opensky_interval {
+            config.opensky.interval = interval;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headless_overrides_config() {
+        let mut config = Config {
+            headless: true,
+            ..Config::default()
+        };
+        Cli::parse_from(["tacview_live", "--headless=false"]).apply(&mut config);
+        assert!(!config.headless);
+        Cli::parse_from(["tacview_live", "--headless"]).apply(&mut config);
+        assert!(config.headless);
+    }
+
+    #[test]
+    fn zero_interval_is_rejected() {
+        assert!(Cli::try_parse_from(["tacview_live", "--opensky-interval", "0"]).is_err());
+        assert!(Cli::try_parse_from(["tacview_live", "--opensky-interval", "5"]).is_ok());
+    }
+}
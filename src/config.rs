@@ -0,0 +1,386 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use bevy::prelude::*;
+use bevy::time::common_conditions::on_real_timer;
+use serde::Deserialize;
+
+/// default location of the config file, next to `.env`
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// how often the config file is checked for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+const OPENSKY_TOKEN_URL: &str =
+    "https://auth.opensky-network.org/auth/realms/opensky-network/protocol/openid-connect/token";
+
+/// Host configuration loaded from a TOML file at startup.
+#[derive(Resource, Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Run without window, renderer and inspector UI.
+    pub headless: bool,
+    /// Address the Tacview real-time telemetry server listens on.
+    pub listen: String,
+    pub opensky: OpenSkyConfig,
+    pub aisstream: AISStreamConfig,
+    pub sbs: SbsConfig,
+    pub beast: BeastConfig,
+    pub nmea: NmeaConfig,
+    pub readsb: ReadsbConfig,
+    pub ogn: OgnConfig,
+    pub mavlink: MavlinkConfig,
+    /// Named areas of interest, every source restricts its traffic to these.
+    pub areas: Vec<Area>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            headless: false,
+            listen: "tcp://0.0.0.0:42674".to_string(),
+            opensky: OpenSkyConfig::default(),
+            aisstream: AISStreamConfig::default(),
+            sbs: SbsConfig::default(),
+            beast: BeastConfig::default(),
+            nmea: NmeaConfig::default(),
+            readsb: ReadsbConfig::default(),
+            ogn: OgnConfig::default(),
+            mavlink: MavlinkConfig::default(),
+            areas: default_areas(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OpenSkyConfig {
+    pub enabled: bool,
+    /// Seconds between two state requests.
+    pub interval: u64,
+    /// How to log in with the credentials from the environment.
+    pub auth: OpenSkyAuth,
+    /// OAuth2 token endpoint used by [`OpenSkyAuth::OAuth2`].
+    pub token_url: String,
+    /// Request the track of every new aircraft and show its past waypoints.
+    pub backfill_tracks: bool,
+    /// Upper limit of track requests per hour.
+    pub max_track_requests: u32,
+    /// Request the last flight of every new aircraft for its departure and arrival airport.
+    pub lookup_flights: bool,
+    /// Upper limit of flight requests per hour.
+    pub max_flight_requests: u32,
+    /// ICAO codes of airports whose arrivals and departures are requested every hour.
+    pub airports: Vec<String>,
+    /// Request the states seen by the own receivers from `/states/own` instead of the areas.
+    pub own_receivers: bool,
+    /// Serials of the own receivers to request, all of them if empty.
+    pub serials: Vec<u64>,
+}
+
+impl Default for OpenSkyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval: 10,
+            auth: OpenSkyAuth::default(),
+            token_url: OPENSKY_TOKEN_URL.to_string(),
+            backfill_tracks: false,
+            max_track_requests: 100,
+            lookup_flights: false,
+            max_flight_requests: 100,
+            airports: Vec::new(),
+            own_receivers: false,
+            serials: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OpenSkyAuth {
+    /// `OPENSKY_USERNAME` and `OPENSKY_PASSWORD`
+    #[default]
+    Basic,
+    /// `OPENSKY_CLIENT_ID` and `OPENSKY_CLIENT_SECRET`
+    OAuth2,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AISStreamConfig {
+    pub enabled: bool,
+    /// Websocket endpoint, can point to a local stand-in.
+    pub url: String,
+    /// Only subscribe to these vessels, all if empty.
+    pub mmsi: Vec<u32>,
+    /// Only subscribe to these message types, e.g. "PositionReport", all if empty.
+    pub message_types: Vec<String>,
+}
+
+impl Default for AISStreamConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            url: "wss://stream.aisstream.io/v0/stream".to_string(),
+            mmsi: Vec::new(),
+            message_types: Vec::new(),
+        }
+    }
+}
+
+/// SBS-1 BaseStation feed of a local ADS-B receiver.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SbsConfig {
+    pub enabled: bool,
+    /// Address of the feed, dump1090 and readsb serve it on port 30003.
+    pub address: String,
+}
+
+impl Default for SbsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "tcp://127.0.0.1:30003".to_string(),
+        }
+    }
+}
+
+/// Raw Mode-S feed of a local ADS-B receiver, decoded in-process.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BeastConfig {
+    pub enabled: bool,
+    /// Address of the feed, Beast is served on port 30005 and AVR on port 30002.
+    pub address: String,
+    pub format: RawFormat,
+}
+
+impl Default for BeastConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "tcp://127.0.0.1:30005".to_string(),
+            format: RawFormat::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RawFormat {
+    /// Beast binary frames
+    #[default]
+    Beast,
+    /// `*<hex>;` lines, optionally with a `@<timestamp>` prefix
+    Avr,
+}
+
+/// NMEA 0183 AIVDM/AIVDO sentences of a local AIS receiver.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NmeaConfig {
+    pub enabled: bool,
+    /// `udp://` addresses are listened on, `tcp://` addresses are connected to.
+    pub address: String,
+}
+
+impl Default for NmeaConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "udp://0.0.0.0:10110".to_string(),
+        }
+    }
+}
+
+/// `aircraft.json` of a readsb / tar1090 instance or a community aggregator.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ReadsbConfig {
+    pub enabled: bool,
+    pub url: String,
+    /// Seconds between two requests.
+    pub interval: u64,
+}
+
+impl Default for ReadsbConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: "http://127.0.0.1/tar1090/data/aircraft.json".to_string(),
+            interval: 1,
+        }
+    }
+}
+
+/// OGN / FLARM beacons from an APRS-IS server.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OgnConfig {
+    pub enabled: bool,
+    pub address: String,
+    /// Callsign to log in with, no passcode is needed for read-only access.
+    pub callsign: String,
+}
+
+impl Default for OgnConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "tcp://aprs.glidernet.org:14580".to_string(),
+            callsign: "N0CALL".to_string(),
+        }
+    }
+}
+
+/// MAVLink telemetry of our own vehicles.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MavlinkConfig {
+    pub enabled: bool,
+    /// UDP address to listen on, ground stations and SITL send to port 14550.
+    pub address: String,
+}
+
+impl Default for MavlinkConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "udp://0.0.0.0:14550".to_string(),
+        }
+    }
+}
+
+/// A named bounding box in WGS-84 decimal degrees.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct Area {
+    pub name: String,
+    /// lower bound for the latitude in decimal degrees
+    pub min_lat: f64,
+    /// upper bound for the latitude in decimal degrees
+    pub max_lat: f64,
+    /// lower bound for the longitude in decimal degrees
+    pub min_lon: f64,
+    /// upper bound for the longitude in decimal degrees
+    pub max_lon: f64,
+}
+
+fn default_areas() -> Vec<Area> {
+    vec![Area {
+        name: "south-east-asia".to_string(),
+        min_lat: 3.2063329870791444,
+        max_lat: 29.477861195816843,
+        min_lon: 97.4267578125,
+        max_lon: 141.48193359375003,
+    }]
+}
+
+impl Config {
+    /// Load the config from `path`, falling back to the defaults if the file does not exist.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        match std::fs::read_to_string(path) {
+            Ok(content) => Self::parse(&content)
+                .map_err(|e| format!("failed to parse {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    fn parse(content: &str) -> Result<Self, String> {
+        let config: Self = toml::from_str(content).map_err(|e| e.to_string())?;
+        // leaving out `[[areas]]` keeps the default area, an explicit empty list would
+        // silently stop every area based source
+        if config.areas.is_empty() {
+            return Err("`areas` must list at least one area".to_string());
+        }
+        // a zero interval would request on every frame
+        if config.opensky.interval == 0 {
+            return Err("`opensky.interval` must be at least 1".to_string());
+        }
+        if config.readsb.interval == 0 {
+            return Err("`readsb.interval` must be at least 1".to_string());
+        }
+        Ok(config)
+    }
+}
+
+/// Reloads the config file when it changes. Only the areas and the AISStream filters are applied
+/// while running, everything else needs a restart.
+pub struct ConfigReloadPlugin {
+    pub path: PathBuf,
+}
+
+impl Plugin for ConfigReloadPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ConfigFile {
+            modified: modified(&self.path),
+            path: self.path.clone(),
+        })
+        .add_systems(Update, reload_config.run_if(on_real_timer(RELOAD_INTERVAL)));
+    }
+}
+
+#[derive(Resource, Debug)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    /// modification time of the loaded file
+    pub modified: Option<SystemTime>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn reload_config(mut file: ResMut<ConfigFile>, mut config: ResMut<Config>) {
+    let modified = modified(&file.path);
+    if modified == file.modified {
+        return;
+    }
+    file.modified = modified;
+    let new = match Config::load(&file.path) {
+        Ok(new) => new,
+        Err(e) => {
+            warn!("keeping the current config: {}", e);
+            return;
+        }
+    };
+
+    // reading does not mark the config changed, only the assignments below do
+    if config.areas != new.areas
+        || config.aisstream.mmsi != new.aisstream.mmsi
+        || config.aisstream.message_types != new.aisstream.message_types
+    {
+        info!("reloaded {}", file.path.display());
+        config.areas = new.areas;
+        config.aisstream.mmsi = new.aisstream.mmsi;
+        config.aisstream.message_types = new.aisstream.message_types;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_areas_use_default() {
+        let config = Config::parse("listen = \"tcp://127.0.0.1:42674\"").unwrap();
+        assert_eq!(config.areas, default_areas());
+    }
+
+    #[test]
+    fn empty_areas_are_rejected() {
+        assert!(Config::parse("areas = []").is_err());
+    }
+
+    #[test]
+    fn zero_intervals_are_rejected() {
+        assert!(Config::parse("[opensky]\ninterval = 0").is_err());
+        assert!(Config::parse("[readsb]\ninterval = 0").is_err());
+        assert!(Config::parse("[opensky]\ninterval = 1\n[readsb]\ninterval = 1").is_ok());
+    }
+}
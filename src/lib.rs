@@ -0,0 +1,15 @@
+pub mod adsb;
+pub mod aisstream;
+pub mod beast;
+pub mod cli;
+pub mod config;
+pub mod mavlink;
+pub mod modes;
+pub mod network;
+pub mod nmea;
+pub mod ogn;
+pub mod opensky;
+pub mod readsb;
+pub mod sbs;
+pub mod track;
+pub mod units;
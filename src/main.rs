@@ -1,3 +1,6 @@
+use std::time::Duration;
+
+use bevy::app::ScheduleRunnerPlugin;
 use bevy::log::LogPlugin;
 use bevy::prelude::*;
 use bevy_activation::{ActivationPlugin, TimeoutEvent};
@@ -5,36 +8,152 @@ use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_octopus::plugin::OctopusPlugin;
 use bevy_octopus::prelude::ListenTo;
 use bevy_tacview::{TACVIEW_CHANNEL, TacviewPlugin, TacviewResource};
+use bevy_tacview::record::PropertyList;
 use bevy_tacview::systems::ObjectNeedSync;
 use chrono::Utc;
+use clap::Parser;
 use dotenvy::dotenv;
 
-pub mod aisstream;
-pub mod opensky;
+use tacview_live::{aisstream, beast, config, mavlink, nmea, ogn, opensky, readsb, sbs};
+use tacview_live::{cli::Cli, config::Config};
 
 fn main() {
-    dotenv().expect(".env file not found");
-    let username = std::env::var("OPENSKY_USERNAME").ok();
-    let password = std::env::var("OPENSKY_PASSWORD").ok();
-    let api_key = std::env::var("AISSTREAM_KEY").unwrap();
-    App::new()
-        .add_plugins(DefaultPlugins.set(LogPlugin {
-            filter: "bevy_octopus=trace,tacview_live=debug".to_string(),
-            ..default()
-        }))
-        .add_plugins(WorldInspectorPlugin::new())
-        .add_plugins(opensky::OpenSkyPlugin { username, password })
+    let dotenv_found = dotenv().is_ok();
+    let cli = Cli::parse();
+    // the log plugin is not set up yet, so report straight to stderr
+    let mut config = match Config::load(&cli.config) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    cli.apply(&mut config);
+    let api_key = std::env::var("AISSTREAM_KEY").ok();
+    let mut app = App::new();
+    if config.headless {
+        app.add_plugins(
+            MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+                1.0 / 60.0,
+            ))),
+        )
+        .add_plugins(log_plugin())
+        // nobody can toggle the controller without the inspector, so start polling right away
+        .insert_resource(opensky::OpenSKyController { open: true });
+    } else {
+        app.add_plugins(DefaultPlugins.set(log_plugin()))
+            .add_plugins(WorldInspectorPlugin::new());
+    }
+    if !dotenv_found {
+        info!(".env file not found, reading credentials from the environment only");
+    }
+
+    let mut sources = vec![];
+    if config.opensky.enabled {
+        let credentials = opensky::OpenSkyCredentials::from_env(&config.opensky);
+        sources.push(match credentials {
+            opensky::OpenSkyCredentials::Anonymous => "OpenSky (anonymous)",
+            opensky::OpenSkyCredentials::Basic { .. } => "OpenSky",
+            opensky::OpenSkyCredentials::OAuth2 { .. } => "OpenSky (OAuth2)",
+        });
+        app.add_plugins(opensky::OpenSkyPlugin {
+            credentials,
+            interval: Duration::from_secs(config.opensky.interval),
+            track_backfill: config
+                .opensky
+                .backfill_tracks
+                .then_some(config.opensky.max_track_requests),
+            flight_lookup: config
+                .opensky
+                .lookup_flights
+                .then_some(config.opensky.max_flight_requests),
+            airports: config.opensky.airports.clone(),
+            own_serials: config
+                .opensky
+                .own_receivers
+                .then(|| config.opensky.serials.clone()),
+        });
+    }
+    if config.aisstream.enabled {
+        if let Some(api_key) = api_key {
+            sources.push("AISStream");
+            app.insert_resource(aisstream::AISStreamResource {
+                api_key,
+                url: config.aisstream.url.clone(),
+            })
+            .add_plugins(aisstream::AISStreamPlugin);
+        } else {
+            warn!("AISSTREAM_KEY is not set, AISStream source disabled");
+        }
+    }
+    if config.sbs.enabled {
+        sources.push("SBS");
+        app.add_plugins(sbs::SbsPlugin {
+            address: config.sbs.address.clone(),
+        });
+    }
+    if config.beast.enabled {
+        sources.push("Beast");
+        app.add_plugins(beast::BeastPlugin {
+            address: config.beast.address.clone(),
+            format: config.beast.format,
+        });
+    }
+    if config.nmea.enabled {
+        sources.push("NMEA");
+        app.add_plugins(nmea::NmeaPlugin {
+            address: config.nmea.address.clone(),
+        });
+    }
+    if config.readsb.enabled {
+        sources.push("readsb");
+        app.add_plugins(readsb::ReadsbPlugin {
+            url: config.readsb.url.clone(),
+            interval: Duration::from_secs(config.readsb.interval),
+        });
+    }
+    if config.ogn.enabled {
+        sources.push("OGN");
+        app.add_plugins(ogn::OgnPlugin {
+            address: config.ogn.address.clone(),
+            callsign: config.ogn.callsign.clone(),
+        });
+    }
+    if config.mavlink.enabled {
+        sources.push("MAVLink");
+        app.add_plugins(mavlink::MavlinkPlugin {
+            address: config.mavlink.address.clone(),
+        });
+    }
+    if sources.is_empty() {
+        warn!("no data source is active");
+    } else {
+        info!("active sources: {}", sources.join(", "));
+    }
+
+    app.insert_resource(config)
+        .add_plugins(config::ConfigReloadPlugin {
+            path: cli.config.clone(),
+        })
         .add_plugins(ActivationPlugin)
         .add_plugins(OctopusPlugin)
         .add_plugins(TacviewPlugin)
-        .insert_resource(aisstream::AISStreamResource { api_key })
-        .add_plugins(aisstream::AISStreamPlugin)
         .add_systems(Startup, setup)
         .add_systems(Update, watch_timeout)
         .run()
 }
-//
-fn setup(mut host_res: ResMut<TacviewResource>, mut commands: Commands) {
+
+fn log_plugin() -> LogPlugin {
+    LogPlugin {
+        filter: "bevy_octopus=trace,tacview_live=debug".to_string(),
+        ..default()
+    }
+}
+
+fn setup(mut host_res: ResMut<TacviewResource>, config: Res<Config>, mut commands: Commands) {
+    for area in config.areas.iter() {
+        info!("area of interest: {:?}", area);
+    }
     *host_res = TacviewResource {
         title: "bevy tacview sample".to_string(),
         category: "test".to_string(),
@@ -47,12 +166,23 @@ fn setup(mut host_res: ResMut<TacviewResource>, mut commands: Commands) {
         data_source: "Tacview".to_string(),
         data_recorder: "TacviewHost Example".to_string(),
     };
-    commands.spawn((TACVIEW_CHANNEL, ListenTo::new("tcp://0.0.0.0:42674")));
+    commands.spawn((TACVIEW_CHANNEL, ListenTo::new(&config.listen)));
 }
 
-fn watch_timeout(mut ev_timeout: EventReader<TimeoutEvent>, mut commands: Commands) {
+fn watch_timeout(
+    mut ev_timeout: EventReader<TimeoutEvent>,
+    q_synced: Query<Has<PropertyList>>,
+    mut commands: Commands,
+) {
     for timeout in ev_timeout.read() {
         debug!("Timeout: {:?}", timeout);
-        commands.entity(timeout.0).insert(ObjectNeedSync::Destroy);
+        match q_synced.get(timeout.0) {
+            Ok(true) => {
+                commands.entity(timeout.0).insert(ObjectNeedSync::Destroy);
+            }
+            // never shown in Tacview, e.g. an aircraft that did not send a position
+            Ok(false) => commands.entity(timeout.0).despawn(),
+            Err(_) => {}
+        }
     }
 }
@@ -0,0 +1,608 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_octopus::prelude::*;
+use bevy_tacview::record::{Coords, Property, Tag};
+
+use crate::network::log_connection;
+use crate::track::{TrackKey, TrackPlugin, TrackSource, TrackState, Tracks};
+
+const MAVLINK_CHANNEL: ChannelId = ChannelId("MAVLink");
+
+const MAGIC_V1: u8 = 0xFE;
+const MAGIC_V2: u8 = 0xFD;
+/// the v2 incompat flag of signed packets, they carry a 13 byte signature after the checksum
+const IFLAG_SIGNED: u8 = 0x01;
+/// MAV_AUTOPILOT_INVALID, sent by components that are not a vehicle (GCS, gimbal, camera)
+const AUTOPILOT_INVALID: u8 = 8;
+
+/// Decodes the MAVLink telemetry of our own vehicles, as sent by an autopilot, a SITL or a
+/// telemetry radio bridge.
+pub struct MavlinkPlugin {
+    /// UDP address to listen on, e.g. udp://0.0.0.0:14550
+    pub address: String,
+}
+
+impl Plugin for MavlinkPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(MavlinkResource {
+            address: self.address.clone(),
+        })
+        .register_type::<MavVehicle>()
+        .add_systems(Startup, setup)
+        .add_systems(Update, (log_connection(MAVLINK_CHANNEL), handle_raw_packet))
+        .add_plugins(TrackPlugin::<MavVehicle>::default());
+    }
+}
+
+#[derive(Resource)]
+pub struct MavlinkResource {
+    pub address: String,
+}
+
+/// listen for telemetry datagrams
+fn setup(res: Res<MavlinkResource>, mut commands: Commands) {
+    commands.spawn((MAVLINK_CHANNEL, ListenTo::new(&res.address)));
+}
+
+/// MAVLink system id of a vehicle
+#[derive(Debug, Component, Reflect, Clone, PartialEq, Eq, Hash)]
+pub struct MavKey(pub u8);
+
+impl TrackKey for MavKey {}
+
+/// Latest telemetry of one vehicle, merged from the messages of its system id.
+#[derive(Debug, Component, Reflect, Clone, PartialEq)]
+pub struct MavVehicle {
+    pub system_id: u8,
+    /// MAV_TYPE of the autopilot heartbeat
+    pub vehicle_type: Option<u8>,
+    pub armed: bool,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// altitude above mean sea level in meters
+    pub altitude: Option<f64>,
+    /// roll, pitch and yaw in degrees
+    pub roll: Option<f64>,
+    pub pitch: Option<f64>,
+    pub yaw: Option<f64>,
+    /// heading in degrees clockwise from north
+    pub heading: Option<f64>,
+    /// indicated airspeed in m/s
+    pub airspeed: Option<f64>,
+    /// ground speed in m/s
+    pub ground_speed: Option<f64>,
+    /// climb rate in m/s
+    pub climb_rate: Option<f64>,
+}
+
+impl MavVehicle {
+    fn empty(system_id: u8) -> Self {
+        Self {
+            system_id,
+            vehicle_type: None,
+            armed: false,
+            latitude: None,
+            longitude: None,
+            altitude: None,
+            roll: None,
+            pitch: None,
+            yaw: None,
+            heading: None,
+            airspeed: None,
+            ground_speed: None,
+            climb_rate: None,
+        }
+    }
+}
+
+/// The decoded messages, converted to SI units, angles in radians as sent.
+#[derive(Debug, PartialEq)]
+pub enum MavMessage {
+    /// HEARTBEAT (#0)
+    Heartbeat {
+        vehicle_type: u8,
+        autopilot: u8,
+        base_mode: u8,
+    },
+    /// ATTITUDE (#30)
+    Attitude { roll: f64, pitch: f64, yaw: f64 },
+    /// GLOBAL_POSITION_INT (#33)
+    GlobalPositionInt {
+        latitude: f64,
+        longitude: f64,
+        altitude: f64,
+        heading: Option<f64>,
+    },
+    /// VFR_HUD (#74)
+    VfrHud {
+        airspeed: f64,
+        ground_speed: f64,
+        climb_rate: f64,
+    },
+}
+
+impl MavMessage {
+    /// CRC_EXTRA seed and payload length of the supported messages, from the common dialect.
+    fn definition(msg_id: u32) -> Option<(u8, usize)> {
+        match msg_id {
+            0 => Some((50, 9)),
+            30 => Some((39, 28)),
+            33 => Some((104, 28)),
+            74 => Some((20, 20)),
+            _ => None,
+        }
+    }
+
+    /// Decode a payload that was zero-padded to its full length, fields are little endian and
+    /// ordered by size.
+    fn decode(msg_id: u32, payload: &[u8]) -> Option<Self> {
+        let u16_at = |i: usize| u16::from_le_bytes([payload[i], payload[i + 1]]);
+        let i32_at = |i: usize| i32::from_le_bytes(payload[i..i + 4].try_into().unwrap());
+        let f32_at = |i: usize| f32::from_le_bytes(payload[i..i + 4].try_into().unwrap()) as f64;
+        match msg_id {
+            0 => Some(Self::Heartbeat {
+                vehicle_type: payload[4],
+                autopilot: payload[5],
+                base_mode: payload[6],
+            }),
+            30 => Some(Self::Attitude {
+                roll: f32_at(4),
+                pitch: f32_at(8),
+                yaw: f32_at(12),
+            }),
+            33 => {
+                let heading = u16_at(26);
+                Some(Self::GlobalPositionInt {
+                    latitude: i32_at(4) as f64 / 1e7,
+                    longitude: i32_at(8) as f64 / 1e7,
+                    // millimeters
+                    altitude: i32_at(12) as f64 / 1000.0,
+                    // centidegrees, u16::MAX if unknown
+                    heading: (heading != u16::MAX).then_some(heading as f64 / 100.0),
+                })
+            }
+            74 => Some(Self::VfrHud {
+                airspeed: f32_at(0),
+                ground_speed: f32_at(4),
+                climb_rate: f32_at(12),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// X.25 CRC-16/MCRF4XX as used by MAVLink
+fn crc_accumulate(crc: u16, byte: u8) -> u16 {
+    let mut tmp = byte ^ (crc & 0xFF) as u8;
+    tmp ^= tmp << 4;
+    let tmp = tmp as u16;
+    (crc >> 8) ^ (tmp << 8) ^ (tmp << 3) ^ (tmp >> 4)
+}
+
+/// Return the supported messages of one UDP datagram with their system id. A datagram carries
+/// whole packets, so nothing is kept for the next one. Packets with a bad checksum, an unknown
+/// message id or a length beyond the datagram are skipped by resyncing on the next magic byte,
+/// as their length can not be trusted without the checksum.
+fn parse_datagram(datagram: &[u8]) -> Vec<(u8, MavMessage)> {
+    let mut messages = vec![];
+    let mut start = 0;
+    while let Some(offset) = datagram[start..]
+        .iter()
+        .position(|b| *b == MAGIC_V1 || *b == MAGIC_V2)
+    {
+        start += offset;
+        let Some(&len) = datagram.get(start + 1) else {
+            break;
+        };
+        let len = len as usize;
+        let v2 = datagram[start] == MAGIC_V2;
+        let (header_len, signature_len) = if v2 {
+            let Some(&incompat_flags) = datagram.get(start + 2) else {
+                break;
+            };
+            let signed = incompat_flags & IFLAG_SIGNED != 0;
+            (10, if signed { 13 } else { 0 })
+        } else {
+            (6, 0)
+        };
+        let packet_len = header_len + len + 2 + signature_len;
+        let Some(packet) = datagram.get(start..start + packet_len) else {
+            start += 1;
+            continue;
+        };
+
+        let (system_id, msg_id) = if v2 {
+            (
+                packet[5],
+                u32::from_le_bytes([packet[7], packet[8], packet[9], 0]),
+            )
+        } else {
+            (packet[3], packet[5] as u32)
+        };
+        let Some((crc_extra, payload_len)) = MavMessage::definition(msg_id) else {
+            start += 1;
+            continue;
+        };
+        let crc = packet[1..header_len + len]
+            .iter()
+            .chain([crc_extra].iter())
+            .fold(0xFFFF, |crc, b| crc_accumulate(crc, *b));
+        let received = u16::from_le_bytes([packet[header_len + len], packet[header_len + len + 1]]);
+        if crc != received {
+            // not a packet start after all, resync on the next magic byte
+            start += 1;
+            continue;
+        }
+        // v2 truncates trailing zero bytes of the payload
+        let mut payload = packet[header_len..header_len + len].to_vec();
+        payload.resize(payload_len, 0);
+        if let Some(message) = MavMessage::decode(msg_id, &payload) {
+            messages.push((system_id, message));
+        }
+        start += packet_len;
+    }
+    messages
+}
+
+fn handle_raw_packet(q_server: Query<(&ChannelId, &NetworkNode)>, mut tracks: Tracks<MavVehicle>) {
+    let mut messages = vec![];
+    for (channel_id, net_node) in q_server.iter() {
+        if *channel_id != MAVLINK_CHANNEL {
+            continue;
+        }
+        while let Ok(Some(packet)) = net_node.recv_message_channel.receiver.try_recv() {
+            messages.extend(parse_datagram(&packet.bytes));
+        }
+    }
+
+    tracks.update(messages.into_iter().inspect(|(system_id, message)| {
+        trace!("mavlink {}: {:?}", system_id, message);
+    }));
+}
+
+/// messages are merged into the vehicle of their system id
+impl TrackState for MavVehicle {
+    type Key = MavKey;
+    type Message = (u8, MavMessage);
+
+    fn key((system_id, _): &(u8, MavMessage)) -> MavKey {
+        MavKey(*system_id)
+    }
+
+    /// Spawned by the heartbeat of an autopilot or a position, the heartbeats of a GCS or
+    /// companion computer on the same link do not make a vehicle.
+    fn new((system_id, message): (u8, MavMessage)) -> Option<Self> {
+        match message {
+            MavMessage::Heartbeat { autopilot, .. } if autopilot != AUTOPILOT_INVALID => {}
+            MavMessage::GlobalPositionInt { .. } => {}
+            _ => return None,
+        }
+        let mut vehicle = Self::empty(system_id);
+        vehicle.merge((system_id, message));
+        Some(vehicle)
+    }
+
+    fn merge(&mut self, (_, message): (u8, MavMessage)) {
+        match message {
+            MavMessage::Heartbeat {
+                vehicle_type,
+                autopilot,
+                base_mode,
+            } => {
+                if autopilot != AUTOPILOT_INVALID {
+                    self.vehicle_type = Some(vehicle_type);
+                    // MAV_MODE_FLAG_SAFETY_ARMED
+                    self.armed = base_mode & 0x80 != 0;
+                }
+            }
+            MavMessage::Attitude { roll, pitch, yaw } => {
+                self.roll = Some(roll.to_degrees());
+                self.pitch = Some(pitch.to_degrees());
+                self.yaw = Some(yaw.to_degrees().rem_euclid(360.0));
+            }
+            MavMessage::GlobalPositionInt {
+                latitude,
+                longitude,
+                altitude,
+                heading,
+            } => {
+                self.latitude = Some(latitude);
+                self.longitude = Some(longitude);
+                self.altitude = Some(altitude);
+                if heading.is_some() {
+                    self.heading = heading;
+                }
+            }
+            MavMessage::VfrHud {
+                airspeed,
+                ground_speed,
+                climb_rate,
+            } => {
+                self.airspeed = Some(airspeed);
+                self.ground_speed = Some(ground_speed);
+                self.climb_rate = Some(climb_rate);
+            }
+        }
+    }
+}
+
+impl TrackSource for MavVehicle {
+    type Key = MavKey;
+    type Data = &'static MavVehicle;
+    type Changed = Changed<MavVehicle>;
+
+    const TIMEOUT: Option<Duration> = Some(Duration::from_secs(10));
+
+    fn coords(vehicle: &&MavVehicle) -> Coords {
+        Coords {
+            longitude: vehicle.longitude,
+            latitude: vehicle.latitude,
+            altitude: vehicle.altitude,
+            u: None,
+            v: None,
+            roll: vehicle.roll,
+            pitch: vehicle.pitch,
+            yaw: vehicle.yaw,
+            heading: vehicle.heading,
+        }
+    }
+
+    fn props(vehicle: &&MavVehicle) -> Vec<Property> {
+        let (name, tags) = vehicle_type(vehicle.vehicle_type);
+        let mut list = vec![
+            Property::Name(name.to_string()),
+            Property::CallSign(format!("MAV{}", vehicle.system_id)),
+            Property::Type(HashSet::from_iter(tags)),
+        ];
+
+        if let Some(airspeed) = vehicle.airspeed {
+            list.push(Property::IAS(airspeed));
+        }
+        if let Some(ground_speed) = vehicle.ground_speed {
+            list.push(Property::Unknown(
+                "GroundSpeed".to_string(),
+                format!("{:.1}", ground_speed),
+            ));
+        }
+        list.push(Property::Unknown(
+            "Armed".to_string(),
+            vehicle.armed.to_string(),
+        ));
+
+        list
+    }
+}
+
+/// Name and Tacview tags of a MAV_TYPE, Tacview has no UAV tag so the vehicles are marked as
+/// minor objects of their basic type.
+fn vehicle_type(vehicle_type: Option<u8>) -> (&'static str, Vec<Tag>) {
+    match vehicle_type.unwrap_or(0) {
+        1 => ("Fixed Wing UAV", vec![Tag::Air, Tag::FixedWing, Tag::Minor]),
+        2 => ("Quadrotor", vec![Tag::Air, Tag::Rotorcraft, Tag::Minor]),
+        3 => ("Coaxial", vec![Tag::Air, Tag::Rotorcraft, Tag::Minor]),
+        4 => (
+            "Helicopter UAV",
+            vec![Tag::Air, Tag::Rotorcraft, Tag::Minor],
+        ),
+        // lighter-than-air, no basic type as it has neither wings nor rotors
+        7 => ("Airship UAV", vec![Tag::Air, Tag::Minor]),
+        8 => ("Balloon", vec![Tag::Air, Tag::Minor]),
+        10 => ("Rover", vec![Tag::Ground, Tag::Vehicle, Tag::Minor]),
+        11 => ("Surface Boat", vec![Tag::Sea, Tag::Watercraft, Tag::Minor]),
+        13 => ("Hexarotor", vec![Tag::Air, Tag::Rotorcraft, Tag::Minor]),
+        14 => ("Octorotor", vec![Tag::Air, Tag::Rotorcraft, Tag::Minor]),
+        15 => ("Tricopter", vec![Tag::Air, Tag::Rotorcraft, Tag::Minor]),
+        16 => (
+            "Flapping Wing UAV",
+            vec![Tag::Air, Tag::FixedWing, Tag::Minor],
+        ),
+        19..=22 => ("VTOL UAV", vec![Tag::Air, Tag::FixedWing, Tag::Minor]),
+        29 => ("Dodecarotor", vec![Tag::Air, Tag::Rotorcraft, Tag::Minor]),
+        _ => ("UAV", vec![Tag::Air, Tag::FixedWing, Tag::Minor]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heartbeat(vehicle_type: u8, autopilot: u8) -> Vec<u8> {
+        vec![0, 0, 0, 0, vehicle_type, autopilot, 0x80, 4, 3]
+    }
+
+    fn global_position(latitude: i32, longitude: i32, altitude: i32) -> Vec<u8> {
+        let mut payload = vec![0; 4];
+        payload.extend(latitude.to_le_bytes());
+        payload.extend(longitude.to_le_bytes());
+        payload.extend(altitude.to_le_bytes());
+        payload.extend([0; 14]);
+        payload
+    }
+
+    fn checksum(packet: &[u8], crc_extra: u8) -> [u8; 2] {
+        packet[1..]
+            .iter()
+            .chain([crc_extra].iter())
+            .fold(0xFFFF, |crc, b| crc_accumulate(crc, *b))
+            .to_le_bytes()
+    }
+
+    fn packet_v1(system_id: u8, msg_id: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![MAGIC_V1, payload.len() as u8, 0, system_id, 1, msg_id];
+        packet.extend(payload);
+        let crc_extra = MavMessage::definition(msg_id as u32).map_or(0, |(crc_extra, _)| crc_extra);
+        packet.extend(checksum(&packet, crc_extra));
+        packet
+    }
+
+    fn packet_v2(system_id: u8, msg_id: u32, payload: &[u8], signed: bool) -> Vec<u8> {
+        // trailing zero bytes are not sent
+        let len = payload.iter().rposition(|b| *b != 0).map_or(1, |i| i + 1);
+        let incompat_flags = if signed { IFLAG_SIGNED } else { 0 };
+        let mut packet = vec![MAGIC_V2, len as u8, incompat_flags, 0, 0, system_id, 1];
+        packet.extend(&msg_id.to_le_bytes()[..3]);
+        packet.extend(&payload[..len]);
+        let crc_extra = MavMessage::definition(msg_id).map_or(0, |(crc_extra, _)| crc_extra);
+        packet.extend(checksum(&packet, crc_extra));
+        if signed {
+            packet.extend([0xAA; 13]);
+        }
+        packet
+    }
+
+    #[test]
+    fn v1_heartbeat() {
+        assert_eq!(
+            parse_datagram(&packet_v1(1, 0, &heartbeat(2, 3))),
+            vec![(
+                1,
+                MavMessage::Heartbeat {
+                    vehicle_type: 2,
+                    autopilot: 3,
+                    base_mode: 0x80,
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn v2_truncated_payload() {
+        let datagram = packet_v2(
+            2,
+            33,
+            &global_position(473_977_418, 85_455_939, 488_000),
+            false,
+        );
+        // the zeroed heading and velocities are cut off
+        assert!(datagram.len() < 10 + 28 + 2);
+        assert_eq!(
+            parse_datagram(&datagram),
+            vec![(
+                2,
+                MavMessage::GlobalPositionInt {
+                    latitude: 47.3977418,
+                    longitude: 8.5455939,
+                    altitude: 488.0,
+                    heading: Some(0.0),
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn v2_signed() {
+        let mut datagram = packet_v2(3, 0, &heartbeat(1, 3), true);
+        datagram.extend(packet_v2(3, 0, &heartbeat(1, 3), false));
+        assert_eq!(parse_datagram(&datagram).len(), 2);
+    }
+
+    #[test]
+    fn bad_checksum_is_skipped() {
+        let mut corrupted = packet_v1(1, 0, &heartbeat(2, 3));
+        corrupted[8] ^= 0xFF;
+        let mut datagram = corrupted;
+        datagram.extend(packet_v1(4, 0, &heartbeat(2, 3)));
+        let messages = parse_datagram(&datagram);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].0, 4);
+    }
+
+    #[test]
+    fn unknown_message_is_skipped() {
+        let mut datagram = packet_v2(1, 253, b"statustext", false);
+        datagram.extend(packet_v2(5, 0, &heartbeat(2, 3), false));
+        let messages = parse_datagram(&datagram);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].0, 5);
+    }
+
+    #[test]
+    fn stray_magic_byte_is_skipped() {
+        // claims a 255 byte payload that the datagram does not have
+        let mut datagram = vec![MAGIC_V1, 255, 0, 1];
+        datagram.extend(packet_v1(6, 0, &heartbeat(2, 3)));
+        let messages = parse_datagram(&datagram);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].0, 6);
+    }
+
+    #[test]
+    fn truncated_packet_is_dropped() {
+        let packet = packet_v1(1, 0, &heartbeat(2, 3));
+        assert!(parse_datagram(&packet[..8]).is_empty());
+    }
+
+    #[test]
+    fn only_autopilots_spawn_vehicles() {
+        let gcs = MavMessage::Heartbeat {
+            vehicle_type: 6,
+            autopilot: AUTOPILOT_INVALID,
+            base_mode: 0,
+        };
+        let attitude = MavMessage::Attitude {
+            roll: 0.0,
+            pitch: 0.0,
+            yaw: 0.0,
+        };
+        let autopilot = MavMessage::Heartbeat {
+            vehicle_type: 2,
+            autopilot: 3,
+            base_mode: 0,
+        };
+        assert_eq!(MavVehicle::new((1, gcs)), None);
+        assert_eq!(MavVehicle::new((1, attitude)), None);
+        assert_eq!(
+            MavVehicle::new((1, autopilot)).and_then(|vehicle| vehicle.vehicle_type),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn receives_udp_telemetry() {
+        // a free port for the plugin to listen on
+        let port = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(bevy_octopus::plugin::OctopusPlugin)
+            .add_plugins(MavlinkPlugin {
+                address: format!("udp://127.0.0.1:{port}"),
+            });
+
+        let sender = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let heartbeat = packet_v1(7, 0, &heartbeat(2, 3));
+        // one bad packet in front of the position, every datagram is parsed on its own
+        let mut position = vec![MAGIC_V2, 255, 0];
+        position.extend(packet_v2(
+            7,
+            33,
+            &global_position(473_977_418, 85_455_939, 488_000),
+            false,
+        ));
+
+        // the socket may not be bound yet, datagrams sent before are lost
+        let started = std::time::Instant::now();
+        let latitude = loop {
+            assert!(started.elapsed() < Duration::from_secs(5), "no telemetry");
+            sender.send_to(&heartbeat, ("127.0.0.1", port)).unwrap();
+            sender.send_to(&position, ("127.0.0.1", port)).unwrap();
+            std::thread::sleep(Duration::from_millis(20));
+            app.update();
+            let latitude = app
+                .world
+                .resource::<crate::track::TrackIndex<MavKey>>()
+                .get(&MavKey(7))
+                .and_then(|entity| app.world.get::<MavVehicle>(*entity))
+                .and_then(|vehicle| vehicle.latitude);
+            if let Some(latitude) = latitude {
+                break latitude;
+            }
+        };
+        assert_eq!(latitude, 47.3977418);
+    }
+}
@@ -0,0 +1,458 @@
+//! Mode-S / ADS-B decoder for raw 56 and 112 bit replies.
+//!
+//! Decodes DF17/DF18 extended squitter identification, airborne position (with global CPR
+//! decoding of even/odd frame pairs), surface position and airborne velocity, and the squawk of
+//! DF5/DF21 identity replies of aircraft already known from their squitters.
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use crate::units::{FEET_PER_MINUTE, FOOT, KNOT};
+
+/// even and odd CPR frames further apart than this can not be combined
+const CPR_MAX_AGE: f64 = 10.0;
+
+const CALLSIGN_CHARS: &[u8; 64] =
+    b"#ABCDEFGHIJKLMNOPQRSTUVWXYZ##### ###############0123456789######";
+
+/// What one Mode-S reply told about an aircraft.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModeSMessage {
+    /// ICAO 24-bit address
+    pub icao24: u32,
+    pub kind: ModeSKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModeSKind {
+    Identification {
+        callsign: String,
+        /// ADS-B emitter category, numbered like the OpenSky category field
+        category: u32,
+    },
+    AirbornePosition {
+        /// decoded position, `None` until an even/odd frame pair arrived
+        position: Option<(f64, f64)>,
+        /// barometric altitude in meters
+        baro_altitude: Option<f64>,
+        /// GNSS height in meters
+        geo_altitude: Option<f64>,
+    },
+    SurfacePosition,
+    Velocity {
+        /// ground speed (or airspeed if no ground speed is available) in m/s
+        velocity: Option<f64>,
+        /// track (or heading) in degrees clockwise from north
+        track: Option<f64>,
+        /// vertical rate in m/s, positive when climbing
+        vertical_rate: Option<f64>,
+    },
+    Squawk(String),
+}
+
+/// Last even and odd CPR frame of one aircraft.
+#[derive(Debug, Default, Clone)]
+struct CprFrames {
+    even: Option<CprFrame>,
+    odd: Option<CprFrame>,
+    last_seen: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CprFrame {
+    lat: u32,
+    lon: u32,
+    time: f64,
+}
+
+/// Stateful decoder, keeps the CPR frames of every aircraft seen in an extended squitter.
+#[derive(Debug, Default)]
+pub struct ModeSDecoder {
+    aircraft: HashMap<u32, CprFrames>,
+}
+
+impl ModeSDecoder {
+    /// Decode one 7 or 14 byte Mode-S reply received at `now` seconds.
+    pub fn decode(&mut self, frame: &[u8], now: f64) -> Option<ModeSMessage> {
+        if frame.len() != 7 && frame.len() != 14 {
+            return None;
+        }
+        let df = frame[0] >> 3;
+        match df {
+            17 | 18 if frame.len() == 14 => {
+                // DF18 with CF != 0 carries TIS-B / ADS-R with a non-ICAO address
+                if df == 18 && frame[0] & 0x07 != 0 {
+                    return None;
+                }
+                if crc_residual(frame) != 0 {
+                    return None;
+                }
+                let icao24 = bits(frame, 8, 24) as u32;
+                let frames = self.aircraft.entry(icao24).or_default();
+                frames.last_seen = now;
+                let kind = decode_extended_squitter(&frame[4..11], frames, now)?;
+                Some(ModeSMessage { icao24, kind })
+            }
+            5 | 21 => {
+                // the parity is overlaid with the address, only trust known aircraft
+                let icao24 = crc_residual(frame);
+                let frames = self.aircraft.get_mut(&icao24)?;
+                frames.last_seen = now;
+                let squawk = decode_squawk(bits(frame, 19, 13) as u32);
+                Some(ModeSMessage {
+                    icao24,
+                    kind: ModeSKind::Squawk(squawk),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// forget aircraft not heard from for `max_age` seconds
+    pub fn prune(&mut self, now: f64, max_age: f64) {
+        self.aircraft
+            .retain(|_, frames| now - frames.last_seen <= max_age);
+    }
+}
+
+fn decode_extended_squitter(me: &[u8], frames: &mut CprFrames, now: f64) -> Option<ModeSKind> {
+    let tc = me[0] >> 3;
+    match tc {
+        1..=4 => {
+            let callsign = (0..8)
+                .map(|i| CALLSIGN_CHARS[bits(me, 8 + 6 * i, 6) as usize] as char)
+                .collect::<String>()
+                .replace('#', "")
+                .trim()
+                .to_string();
+            let category = emitter_category(tc, me[0] & 0x07);
+            Some(ModeSKind::Identification { callsign, category })
+        }
+        5..=8 => Some(ModeSKind::SurfacePosition),
+        9..=18 | 20..=22 => {
+            let altitude = bits(me, 8, 12) as u32;
+            let (baro_altitude, geo_altitude) = if tc <= 18 {
+                (decode_ac12(altitude), None)
+            } else {
+                (None, Some(altitude as f64))
+            };
+
+            let frame = CprFrame {
+                lat: bits(me, 22, 17) as u32,
+                lon: bits(me, 39, 17) as u32,
+                time: now,
+            };
+            let odd = bits(me, 21, 1) == 1;
+            if odd {
+                frames.odd = Some(frame);
+            } else {
+                frames.even = Some(frame);
+            }
+            let position = match (frames.even, frames.odd) {
+                (Some(even), Some(odd_frame))
+                    if (even.time - odd_frame.time).abs() <= CPR_MAX_AGE =>
+                {
+                    cpr_global(even, odd_frame, odd)
+                }
+                _ => None,
+            };
+
+            Some(ModeSKind::AirbornePosition {
+                position,
+                baro_altitude,
+                geo_altitude,
+            })
+        }
+        19 => decode_velocity(me),
+        _ => None,
+    }
+}
+
+/// Map type code and emitter category to the numbering OpenSky uses for its category field.
+pub(crate) fn emitter_category(tc: u8, ca: u8) -> u32 {
+    if ca == 0 {
+        return 1;
+    }
+    match (tc, ca) {
+        // set A: 1 light .. 7 rotorcraft
+        (4, ca) => 1 + ca as u32,
+        // set B: 1 glider .. 7 space vehicle
+        (3, ca) => 8 + ca as u32,
+        (2, 1) => 16,
+        (2, 3) => 17,
+        // set C: 4 point, 5 cluster and 6 line obstacle
+        (2, 4..=6) => 14 + ca as u32,
+        _ => 0,
+    }
+}
+
+/// 12 bit altitude code of airborne positions in meters, only 25 ft increments are supported.
+fn decode_ac12(code: u32) -> Option<f64> {
+    let q = code & 0x10 != 0;
+    if !q || code == 0 {
+        return None;
+    }
+    let n = ((code & 0xFE0) >> 1) | (code & 0x0F);
+    Some((n as f64 * 25.0 - 1000.0) * FOOT)
+}
+
+fn decode_velocity(me: &[u8]) -> Option<ModeSKind> {
+    let subtype = bits(me, 5, 3);
+    let vr = bits(me, 37, 9);
+    let vertical_rate = (vr != 0).then(|| {
+        let rate = (vr as f64 - 1.0) * 64.0 * FEET_PER_MINUTE;
+        if bits(me, 36, 1) == 1 {
+            -rate
+        } else {
+            rate
+        }
+    });
+
+    match subtype {
+        1 | 2 => {
+            let factor = if subtype == 2 { 4.0 } else { 1.0 };
+            let v_ew = bits(me, 14, 10);
+            let v_ns = bits(me, 25, 10);
+            if v_ew == 0 || v_ns == 0 {
+                return Some(ModeSKind::Velocity {
+                    velocity: None,
+                    track: None,
+                    vertical_rate,
+                });
+            }
+            let mut v_ew = (v_ew as f64 - 1.0) * factor;
+            let mut v_ns = (v_ns as f64 - 1.0) * factor;
+            if bits(me, 13, 1) == 1 {
+                v_ew = -v_ew;
+            }
+            if bits(me, 24, 1) == 1 {
+                v_ns = -v_ns;
+            }
+            let speed = (v_ew * v_ew + v_ns * v_ns).sqrt();
+            let track = v_ew.atan2(v_ns).to_degrees().rem_euclid(360.0);
+            Some(ModeSKind::Velocity {
+                velocity: Some(speed * KNOT),
+                track: Some(track),
+                vertical_rate,
+            })
+        }
+        3 | 4 => {
+            let factor = if subtype == 4 { 4.0 } else { 1.0 };
+            let heading = (bits(me, 13, 1) == 1).then(|| bits(me, 14, 10) as f64 * 360.0 / 1024.0);
+            let airspeed = bits(me, 25, 10);
+            let velocity = (airspeed != 0).then_some((airspeed as f64 - 1.0) * factor * KNOT);
+            Some(ModeSKind::Velocity {
+                velocity,
+                track: heading,
+                vertical_rate,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Decode the 13 bit identity code (C1 A1 C2 A2 C4 A4 X B1 D1 B2 D2 B4 D4) to a 4 digit squawk.
+fn decode_squawk(id: u32) -> String {
+    let bit = |n: u32| (id >> (12 - n)) & 1;
+    let a = bit(5) << 2 | bit(3) << 1 | bit(1);
+    let b = bit(11) << 2 | bit(9) << 1 | bit(7);
+    let c = bit(4) << 2 | bit(2) << 1 | bit(0);
+    let d = bit(12) << 2 | bit(10) << 1 | bit(8);
+    format!("{}{}{}{}", a, b, c, d)
+}
+
+/// Global CPR decoding of an airborne even/odd frame pair, the position of the latest frame is
+/// returned.
+fn cpr_global(even: CprFrame, odd: CprFrame, odd_is_latest: bool) -> Option<(f64, f64)> {
+    const CPR_MAX: f64 = 131072.0;
+    let d_lat_even = 360.0 / 60.0;
+    let d_lat_odd = 360.0 / 59.0;
+    let lat_cpr_even = even.lat as f64 / CPR_MAX;
+    let lon_cpr_even = even.lon as f64 / CPR_MAX;
+    let lat_cpr_odd = odd.lat as f64 / CPR_MAX;
+    let lon_cpr_odd = odd.lon as f64 / CPR_MAX;
+
+    let j = (59.0 * lat_cpr_even - 60.0 * lat_cpr_odd + 0.5).floor();
+    let mut lat_even = d_lat_even * (j.rem_euclid(60.0) + lat_cpr_even);
+    let mut lat_odd = d_lat_odd * (j.rem_euclid(59.0) + lat_cpr_odd);
+    if lat_even >= 270.0 {
+        lat_even -= 360.0;
+    }
+    if lat_odd >= 270.0 {
+        lat_odd -= 360.0;
+    }
+    // both frames have to be in the same longitude zone
+    if cpr_nl(lat_even) != cpr_nl(lat_odd) {
+        return None;
+    }
+
+    let (lat, lon_cpr, nl_offset) = if odd_is_latest {
+        (lat_odd, lon_cpr_odd, 1)
+    } else {
+        (lat_even, lon_cpr_even, 0)
+    };
+    let nl = cpr_nl(lat);
+    let ni = (nl - nl_offset).max(1) as f64;
+    let m = (lon_cpr_even * (nl as f64 - 1.0) - lon_cpr_odd * nl as f64 + 0.5).floor();
+    let mut lon = (360.0 / ni) * (m.rem_euclid(ni) + lon_cpr);
+    if lon >= 180.0 {
+        lon -= 360.0;
+    }
+    Some((lat, lon))
+}
+
+/// number of longitude zones at the given latitude
+fn cpr_nl(lat: f64) -> i32 {
+    let lat = lat.abs();
+    if lat < 1e-9 {
+        return 59;
+    }
+    if lat > 87.0 {
+        return 1;
+    }
+    if lat == 87.0 {
+        return 2;
+    }
+    let nz = 15.0;
+    let a = 1.0 - (PI / (2.0 * nz)).cos();
+    let b = (PI / 180.0 * lat).cos().powi(2);
+    (2.0 * PI / (1.0 - a / b).acos()).floor() as i32
+}
+
+/// CRC-24 remainder of the data bits XOR the parity field, 0 for a valid extended squitter and
+/// the ICAO address for address/parity replies.
+pub fn crc_residual(frame: &[u8]) -> u32 {
+    let (data, parity) = frame.split_at(frame.len() - 3);
+    let parity = (parity[0] as u32) << 16 | (parity[1] as u32) << 8 | parity[2] as u32;
+    crc24(data) ^ parity
+}
+
+fn crc24(data: &[u8]) -> u32 {
+    const GENERATOR: u32 = 0x1FFF409;
+    let mut crc = 0u32;
+    for byte in data {
+        crc ^= (*byte as u32) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x1000000 != 0 {
+                crc ^= GENERATOR;
+            }
+        }
+    }
+    crc & 0xFFFFFF
+}
+
+/// `len` bits starting at bit `start`, counted from the most significant bit of `data[0]`
+fn bits(data: &[u8], start: usize, len: usize) -> u64 {
+    (start..start + len).fold(0, |acc, i| {
+        acc << 1 | ((data[i / 8] >> (7 - i % 8)) & 1) as u64
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn crc24_of_extended_squitter() {
+        let frame = frame("8D4840D6202CC371C32CE0576098");
+        assert_eq!(crc24(&frame[..11]), 0x576098);
+        assert_eq!(crc_residual(&frame), 0);
+
+        let mut corrupted = frame.clone();
+        corrupted[5] ^= 0x01;
+        assert_ne!(crc_residual(&corrupted), 0);
+    }
+
+    #[test]
+    fn identification() {
+        let mut decoder = ModeSDecoder::default();
+        let message = decoder
+            .decode(&frame("8D4840D6202CC371C32CE0576098"), 0.0)
+            .unwrap();
+        assert_eq!(message.icao24, 0x4840D6);
+        assert_eq!(
+            message.kind,
+            ModeSKind::Identification {
+                callsign: "KLM1023".to_string(),
+                category: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn ac12_altitude() {
+        // 38000 ft with the Q bit set
+        let altitude = decode_ac12(0xC38).unwrap();
+        assert!((altitude - 38000.0 * FOOT).abs() < 1e-6);
+        // without the Q bit the altitude is in 100 ft Gillham code
+        assert_eq!(decode_ac12(0xC28), None);
+        assert_eq!(decode_ac12(0), None);
+    }
+
+    #[test]
+    fn cpr_global_position() {
+        let mut decoder = ModeSDecoder::default();
+        let odd = decoder
+            .decode(&frame("8D40621D58C386435CC412692AD6"), 0.0)
+            .unwrap();
+        assert!(matches!(
+            odd.kind,
+            ModeSKind::AirbornePosition { position: None, .. }
+        ));
+
+        let even = decoder
+            .decode(&frame("8D40621D58C382D690C8AC2863A7"), 1.0)
+            .unwrap();
+        let ModeSKind::AirbornePosition {
+            position: Some((lat, lon)),
+            baro_altitude: Some(altitude),
+            geo_altitude: None,
+        } = even.kind
+        else {
+            panic!("no position in {:?}", even.kind);
+        };
+        assert!((lat - 52.2572).abs() < 1e-4);
+        assert!((lon - 3.91937).abs() < 1e-4);
+        assert!((altitude - 38000.0 * FOOT).abs() < 1e-6);
+    }
+
+    #[test]
+    fn cpr_frames_too_far_apart() {
+        let mut decoder = ModeSDecoder::default();
+        decoder.decode(&frame("8D40621D58C386435CC412692AD6"), 0.0);
+        let even = decoder
+            .decode(&frame("8D40621D58C382D690C8AC2863A7"), CPR_MAX_AGE + 1.0)
+            .unwrap();
+        assert!(matches!(
+            even.kind,
+            ModeSKind::AirbornePosition { position: None, .. }
+        ));
+    }
+
+    #[test]
+    fn airborne_velocity() {
+        let mut decoder = ModeSDecoder::default();
+        let message = decoder
+            .decode(&frame("8D485020994409940838175B284F"), 0.0)
+            .unwrap();
+        let ModeSKind::Velocity {
+            velocity: Some(velocity),
+            track: Some(track),
+            vertical_rate: Some(vertical_rate),
+        } = message.kind
+        else {
+            panic!("no velocity in {:?}", message.kind);
+        };
+        assert!((velocity - 159.20 * KNOT).abs() < 0.01);
+        assert!((track - 182.88).abs() < 0.01);
+        assert!((vertical_rate + 832.0 * FEET_PER_MINUTE).abs() < 1e-6);
+    }
+}
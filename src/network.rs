@@ -0,0 +1,339 @@
+use std::marker::PhantomData;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+
+use bevy::prelude::*;
+use bevy_octopus::prelude::*;
+
+/// longest partial line kept between packets, the lines of every line based feed are well below
+const MAX_LINE: usize = 1024;
+
+/// Log the connection events of the nodes on `channel`, for sources that only read their node.
+pub fn log_connection(channel: ChannelId) -> impl FnMut(EventReader<NetworkNodeEvent>) {
+    move |mut ev_node: EventReader<NetworkNodeEvent>| {
+        for NetworkNodeEvent {
+            channel_id, event, ..
+        } in ev_node.read()
+        {
+            if *channel_id == channel {
+                log_event(channel_id, event);
+            }
+        }
+    }
+}
+
+pub fn log_event(channel_id: &ChannelId, event: &NetworkEvent) {
+    match event {
+        NetworkEvent::Connected => {
+            info!("{channel_id} Connected");
+        }
+        NetworkEvent::Disconnected => {
+            info!("Disconnected from {}", channel_id);
+        }
+        NetworkEvent::Listen => {
+            info!("{channel_id} Listening");
+        }
+        NetworkEvent::Error(error) => {
+            error!("Error on {}: {:?}", channel_id, error);
+        }
+    }
+}
+
+/// Append `bytes` to `buffer` and take the complete lines off its front, for line based feeds.
+///
+/// A packet may end in the middle of a line, the rest stays in `buffer` for the next packet. A
+/// rest longer than [`MAX_LINE`] is not a line or lost its line ending and is dropped.
+pub fn split_lines(buffer: &mut Vec<u8>, bytes: &[u8]) -> Vec<String> {
+    buffer.extend_from_slice(bytes);
+    let mut lines = vec![];
+    while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+        let line = buffer.drain(..=end).collect::<Vec<_>>();
+        lines.push(String::from_utf8_lossy(&line[..end]).into_owned());
+    }
+    if buffer.len() > MAX_LINE {
+        warn!("discarding {} bytes without a line ending", buffer.len());
+        buffer.clear();
+    }
+    lines
+}
+
+/// HTTP client of one source, the responses come back to the source that sent the request.
+///
+/// An [`HttpResponse`](bevy_http_client::HttpResponse) event reaches every reader, which can only
+/// tell its own by the URL, and a redirect changes that. `T` names the source so every source
+/// gets its own resource.
+#[derive(Resource)]
+pub struct Fetcher<T> {
+    sender: Sender<ehttp::Result<ehttp::Response>>,
+    receiver: Mutex<Receiver<ehttp::Result<ehttp::Response>>>,
+    source: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for Fetcher<T> {
+    fn default() -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            sender,
+            receiver: Mutex::new(receiver),
+            source: PhantomData,
+        }
+    }
+}
+
+impl<T> Fetcher<T> {
+    /// Send `request` in the background, redirects are followed.
+    pub fn fetch(&self, request: ehttp::Request) {
+        let sender = self.sender.clone();
+        ehttp::fetch(request, move |result| {
+            // only fails once the app is gone
+            let _ = sender.send(result);
+        });
+    }
+
+    /// The responses that arrived since the last call, or why the request failed.
+    pub fn responses(&self) -> Vec<ehttp::Result<ehttp::Response>> {
+        match self.receiver.lock() {
+            Ok(receiver) => receiver.try_iter().collect(),
+            Err(_) => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_partial_line() {
+        let mut buffer = vec![];
+        assert_eq!(split_lines(&mut buffer, b"first\r\nsec"), vec!["first\r"]);
+        assert_eq!(split_lines(&mut buffer, b"ond\n"), vec!["second"]);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn websocket_stand_in() {
+        use std::io::{Read, Write};
+
+        // the example handshake of RFC 6455
+        assert_eq!(
+            mock::accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+
+        let (url, messages) = mock::websocket_server();
+        let mut stream = std::net::TcpStream::connect(url.trim_start_matches("ws://")).unwrap();
+        stream
+            .write_all(
+                b"GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+                  Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                  Sec-WebSocket-Version: 13\r\n\r\n",
+            )
+            .unwrap();
+        let mask = [1, 2, 3, 4];
+        let mut frame = vec![0x81, 0x80 | 5];
+        frame.extend(mask);
+        frame.extend(b"hello".iter().zip(mask.iter().cycle()).map(|(b, m)| b ^ m));
+        stream.write_all(&frame).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 101 "));
+        assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+        assert_eq!(
+            messages
+                .recv_timeout(std::time::Duration::from_secs(5))
+                .unwrap(),
+            "hello"
+        );
+    }
+
+    #[test]
+    fn drops_overlong_rest() {
+        let mut buffer = vec![];
+        assert!(split_lines(&mut buffer, &[b'x'; MAX_LINE + 1]).is_empty());
+        assert!(buffer.is_empty());
+        assert_eq!(split_lines(&mut buffer, b"next\n"), vec!["next"]);
+    }
+}
+
+/// Local stand-ins for the servers the sources talk to.
+#[cfg(test)]
+pub(crate) mod mock {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc::{channel, Receiver};
+
+    use base64::prelude::BASE64_STANDARD;
+    use base64::Engine;
+
+    /// Serve HTTP on a free local port until the test ends, `respond` gets the request line and
+    /// returns the whole response. Returns the base URL, e.g. `http://127.0.0.1:40001`.
+    pub fn http_server(respond: impl Fn(&str) -> String + Send + 'static) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut reader = BufReader::new(&stream);
+                let mut request_line = String::new();
+                let _ = reader.read_line(&mut request_line);
+                // read the body too, closing with unread data would reset the connection
+                let mut length = 0;
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap_or(0);
+                        }
+                    }
+                    line.clear();
+                }
+                let _ = reader.read_exact(&mut vec![0; length]);
+                let _ = stream.write_all(respond(&request_line).as_bytes());
+            }
+        });
+        url
+    }
+
+    /// A complete HTTP response with `body`.
+    pub fn response(status: &str, headers: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {status}\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    /// Serve websockets on a free local port until the test ends. The first text message of every
+    /// connection is passed to the returned receiver, then the connection is dropped without a
+    /// close frame like a lost link. Returns the URL, e.g. `ws://127.0.0.1:40001`.
+    pub fn websocket_server() -> (String, Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (sender, receiver) = channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                let mut reader = BufReader::new(&stream);
+                let mut key = None;
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("sec-websocket-key") {
+                            key = Some(value.trim().to_string());
+                        }
+                    }
+                    line.clear();
+                }
+                let Some(key) = key else {
+                    continue;
+                };
+                let _ = (&stream).write_all(
+                    format!(
+                        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                         Connection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                        accept_key(&key)
+                    )
+                    .as_bytes(),
+                );
+                if let Some(text) = read_text(&mut reader) {
+                    let _ = sender.send(text);
+                }
+            }
+        });
+        (url, receiver)
+    }
+
+    /// `Sec-WebSocket-Accept` answer to the `Sec-WebSocket-Key` of a client
+    pub fn accept_key(key: &str) -> String {
+        BASE64_STANDARD.encode(sha1(
+            format!("{key}258EAFA5-E914-47DA-95CA-C5AB0DC85B11").as_bytes(),
+        ))
+    }
+
+    /// Payload of the next text frame, control frames like pings are skipped.
+    fn read_text(reader: &mut impl Read) -> Option<String> {
+        loop {
+            let mut head = [0; 2];
+            reader.read_exact(&mut head).ok()?;
+            let len = match head[1] & 0x7f {
+                126 => {
+                    let mut len = [0; 2];
+                    reader.read_exact(&mut len).ok()?;
+                    u16::from_be_bytes(len) as usize
+                }
+                127 => {
+                    let mut len = [0; 8];
+                    reader.read_exact(&mut len).ok()?;
+                    u64::from_be_bytes(len) as usize
+                }
+                len => len as usize,
+            };
+            // clients always mask their frames
+            let mut mask = [0; 4];
+            if head[1] & 0x80 != 0 {
+                reader.read_exact(&mut mask).ok()?;
+            }
+            let mut payload = vec![0; len];
+            reader.read_exact(&mut payload).ok()?;
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+            if head[0] & 0x0f == 1 {
+                return String::from_utf8(payload).ok();
+            }
+        }
+    }
+
+    /// SHA-1 of the websocket handshake, no crate of the tree has it
+    fn sha1(data: &[u8]) -> [u8; 20] {
+        let mut state: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+        let mut message = data.to_vec();
+        message.push(0x80);
+        while message.len() % 64 != 56 {
+            message.push(0);
+        }
+        message.extend((data.len() as u64 * 8).to_be_bytes());
+        for block in message.chunks(64) {
+            let mut w = [0u32; 80];
+            for (i, word) in block.chunks(4).enumerate() {
+                w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+            }
+            for i in 16..80 {
+                w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+            }
+            let [mut a, mut b, mut c, mut d, mut e] = state;
+            for (i, word) in w.iter().enumerate() {
+                let (f, k) = match i {
+                    0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                    20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                    40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                    _ => (b ^ c ^ d, 0xCA62C1D6),
+                };
+                let temp = a
+                    .rotate_left(5)
+                    .wrapping_add(f)
+                    .wrapping_add(e)
+                    .wrapping_add(k)
+                    .wrapping_add(*word);
+                e = d;
+                d = c;
+                c = b.rotate_left(30);
+                b = a;
+                a = temp;
+            }
+            for (state, value) in state.iter_mut().zip([a, b, c, d, e]) {
+                *state = state.wrapping_add(value);
+            }
+        }
+        let mut digest = [0; 20];
+        for (bytes, value) in digest.chunks_mut(4).zip(state) {
+            bytes.copy_from_slice(&value.to_be_bytes());
+        }
+        digest
+    }
+}
@@ -0,0 +1,664 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_octopus::prelude::*;
+use chrono::Utc;
+
+use crate::aisstream::{
+    ais_text, update_vessel, AISMessageBody, AidsToNavigationReport, ClassBPositionReport,
+    Dimension, Eta, MetaData, PositionReport, ShipStaticData, StaticDataReport, StaticReportA,
+    StaticReportB, VesselKey, VesselMut, VesselPlugin,
+};
+use crate::network::{log_connection, split_lines};
+use crate::track::TrackIndex;
+
+const NMEA_CHANNEL: ChannelId = ChannelId("NMEA");
+
+/// Reads AIVDM/AIVDO sentences of a local AIS receiver, as sent by rtl-ais, AIS-catcher or a
+/// ship's NMEA multiplexer.
+pub struct NmeaPlugin {
+    /// `udp://` addresses are listened on, anything else is connected to, e.g.
+    /// udp://0.0.0.0:10110 or tcp://192.168.1.10:10110
+    pub address: String,
+}
+
+impl Plugin for NmeaPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<VesselPlugin>() {
+            app.add_plugins(VesselPlugin);
+        }
+        app.insert_resource(NmeaResource {
+            address: self.address.clone(),
+        })
+        .add_systems(Startup, setup)
+        .add_systems(Update, (log_connection(NMEA_CHANNEL), handle_raw_packet));
+    }
+}
+
+#[derive(Resource)]
+pub struct NmeaResource {
+    pub address: String,
+}
+
+/// listen for datagrams or connect to the NMEA server
+fn setup(res: Res<NmeaResource>, mut commands: Commands) {
+    if res.address.starts_with("udp://") {
+        commands.spawn((NMEA_CHANNEL, ListenTo::new(&res.address)));
+    } else {
+        commands.spawn((NMEA_CHANNEL, ConnectTo::new(&res.address)));
+    }
+}
+
+fn handle_raw_packet(
+    q_server: Query<(&ChannelId, &NetworkNode)>,
+    mut q_vessels: Query<VesselMut>,
+    mut mssi_index: ResMut<TrackIndex<VesselKey>>,
+    mut buffer: Local<Vec<u8>>,
+    mut assembler: Local<Assembler>,
+    mut commands: Commands,
+) {
+    for (channel_id, net_node) in q_server.iter() {
+        if *channel_id != NMEA_CHANNEL {
+            continue;
+        }
+        while let Ok(Some(packet)) = net_node.recv_message_channel.receiver.try_recv() {
+            for line in split_lines(&mut buffer, &packet.bytes) {
+                let Some(sentence) = Sentence::parse(&line) else {
+                    continue;
+                };
+                let Some((payload, fill_bits)) = assembler.push(sentence) else {
+                    continue;
+                };
+                let Some(report) = AisReport::decode(&payload, fill_bits) else {
+                    continue;
+                };
+                trace!("nmea report: {} {:?}", report.mmsi, report.name);
+
+                let previous = mssi_index
+                    .get(&VesselKey(report.mmsi))
+                    .and_then(|entity| q_vessels.get(*entity).ok())
+                    .map(|vessel| vessel.meta_data.clone());
+                // static data of a vessel without a known position is sent again within minutes
+                let Some((longitude, latitude)) = report.position.or(previous
+                    .as_ref()
+                    .map(|meta_data| (meta_data.longitude, meta_data.latitude)))
+                else {
+                    continue;
+                };
+                let meta_data = MetaData {
+                    mmsi: report.mmsi,
+                    ship_name: report
+                        .name
+                        .or(previous.map(|meta_data| meta_data.ship_name))
+                        .unwrap_or_default(),
+                    longitude,
+                    latitude,
+                    time_utc: Utc::now().format("%Y-%m-%d %H:%M:%S%.f %z UTC").to_string(),
+                };
+
+                let mut bodies = report.bodies;
+                if bodies.is_empty() {
+                    bodies.push(AISMessageBody::Other);
+                }
+                for body in bodies {
+                    update_vessel(
+                        &mut commands,
+                        &mut q_vessels,
+                        &mut mssi_index,
+                        meta_data.clone(),
+                        body,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// One `!AIVDM` (other vessels) or `!AIVDO` (own vessel) sentence.
+#[derive(Debug, PartialEq)]
+struct Sentence {
+    /// number of sentences the message is split into
+    fragments: usize,
+    /// 1-based number of this sentence
+    fragment: usize,
+    /// sequential message id shared by the fragments of one message
+    sequence: String,
+    /// radio channel, A or B
+    channel: String,
+    payload: String,
+    /// number of bits to drop from the end of the last fragment
+    fill_bits: usize,
+}
+
+impl Sentence {
+    /// Parse a sentence and verify its checksum, a leading NMEA 4.0 tag block is skipped.
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        let line = &line[line.find('!')?..];
+        let (body, checksum) = line[1..].split_once('*')?;
+        let checksum = u8::from_str_radix(checksum.get(..2)?, 16).ok()?;
+        if body.bytes().fold(0, |acc, b| acc ^ b) != checksum {
+            return None;
+        }
+
+        let fields = body.split(',').collect::<Vec<_>>();
+        if fields.len() != 7 || !(fields[0].ends_with("VDM") || fields[0].ends_with("VDO")) {
+            return None;
+        }
+        let fragments = fields[1].parse::<usize>().ok()?;
+        let fragment = fields[2].parse::<usize>().ok()?;
+        if fragment == 0 || fragment > fragments {
+            return None;
+        }
+
+        Some(Self {
+            fragments,
+            fragment,
+            sequence: fields[3].to_string(),
+            channel: fields[4].to_string(),
+            payload: fields[5].to_string(),
+            fill_bits: fields[6].parse().ok()?,
+        })
+    }
+}
+
+/// Joins the payloads of multi-sentence messages.
+#[derive(Default)]
+struct Assembler {
+    /// fragments received so far, keyed by sequential message id and channel
+    pending: HashMap<(String, String), Vec<Option<String>>>,
+}
+
+impl Assembler {
+    /// Returns the full payload and its fill bits once the last fragment of a message arrived.
+    fn push(&mut self, sentence: Sentence) -> Option<(String, usize)> {
+        if sentence.fragments == 1 {
+            return Some((sentence.payload, sentence.fill_bits));
+        }
+
+        let key = (sentence.sequence, sentence.channel);
+        if sentence.fragment == 1 {
+            // a new message reuses the id of an old one, drop what is left of the old one
+            self.pending
+                .insert(key.clone(), vec![None; sentence.fragments]);
+        }
+        let parts = self.pending.get_mut(&key)?;
+        if parts.len() != sentence.fragments {
+            self.pending.remove(&key);
+            return None;
+        }
+        parts[sentence.fragment - 1] = Some(sentence.payload);
+        if parts.iter().any(Option::is_none) {
+            return None;
+        }
+
+        let payload = self.pending.remove(&key)?.into_iter().flatten().collect();
+        Some((payload, sentence.fill_bits))
+    }
+}
+
+/// The 6-bit armored payload unpacked to single bits.
+struct Bits(Vec<bool>);
+
+impl Bits {
+    fn unarmor(payload: &str, fill_bits: usize) -> Option<Self> {
+        let mut bits = Vec::with_capacity(payload.len() * 6);
+        for c in payload.bytes() {
+            let value = match c {
+                b'0'..=b'W' => c - b'0',
+                b'`'..=b'w' => c - b'`' + 40,
+                _ => return None,
+            };
+            bits.extend((0..6).rev().map(|i| value >> i & 1 == 1));
+        }
+        bits.truncate(bits.len().saturating_sub(fill_bits));
+        Some(Self(bits))
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Unsigned field, bits missing at the end of short messages read as 0.
+    fn uint(&self, start: usize, len: usize) -> u32 {
+        (start..start + len).fold(0, |acc, i| {
+            acc << 1 | self.0.get(i).copied().unwrap_or_default() as u32
+        })
+    }
+
+    /// Two's complement field.
+    fn int(&self, start: usize, len: usize) -> i32 {
+        let value = self.uint(start, len) as i32;
+        value << (32 - len) >> (32 - len)
+    }
+
+    fn flag(&self, start: usize) -> bool {
+        self.uint(start, 1) == 1
+    }
+
+    /// `len` characters of 6-bit ASCII.
+    fn text(&self, start: usize, len: usize) -> String {
+        (0..len)
+            .map(|i| {
+                let value = self.uint(start + i * 6, 6) as u8;
+                if value < 32 {
+                    (value + 64) as char
+                } else {
+                    value as char
+                }
+            })
+            .collect()
+    }
+
+    /// Position in 1/10000 minutes, `None` if not available (181, 91).
+    fn position(&self, lon: usize, lat: usize) -> Option<(f64, f64)> {
+        let longitude = self.int(lon, 28) as f64 / 600_000.0;
+        let latitude = self.int(lat, 27) as f64 / 600_000.0;
+        (longitude.abs() <= 180.0 && latitude.abs() <= 90.0).then_some((longitude, latitude))
+    }
+
+    fn dimension(&self, start: usize) -> Dimension {
+        Dimension {
+            a: self.uint(start, 9) as i32,
+            b: self.uint(start + 9, 9) as i32,
+            c: self.uint(start + 18, 6) as i32,
+            d: self.uint(start + 24, 6) as i32,
+        }
+    }
+}
+
+/// What one AIS message tells about a vessel.
+struct AisReport {
+    mmsi: i32,
+    name: Option<String>,
+    /// longitude and latitude in decimal degrees
+    position: Option<(f64, f64)>,
+    bodies: Vec<AISMessageBody>,
+}
+
+impl AisReport {
+    /// Decode message types 1, 2, 3, 5, 18, 19, 21, 24 and 27, other types are ignored.
+    fn decode(payload: &str, fill_bits: usize) -> Option<Self> {
+        let bits = Bits::unarmor(payload, fill_bits)?;
+        let message_id = bits.uint(0, 6) as i32;
+        let min_len = match message_id {
+            1..=3 | 18 => 168,
+            5 => 420,
+            19 => 312,
+            21 => 272,
+            24 => 160,
+            27 => 96,
+            _ => return None,
+        };
+        if bits.len() < min_len {
+            return None;
+        }
+        let repeat_indicator = bits.uint(6, 2) as i32;
+        let mmsi = bits.uint(8, 30) as i32;
+        let mut report = Self {
+            mmsi,
+            name: None,
+            position: None,
+            bodies: vec![],
+        };
+
+        match message_id {
+            1..=3 => {
+                report.position = bits.position(61, 89);
+                report
+                    .bodies
+                    .push(AISMessageBody::PositionReport(PositionReport {
+                        message_id,
+                        repeat_indicator,
+                        user_id: mmsi,
+                        valid: true,
+                        navigational_status: bits.uint(38, 4) as i32,
+                        rate_of_turn: bits.int(42, 8),
+                        sog: bits.uint(50, 10) as f64 / 10.0,
+                        position_accuracy: bits.flag(60),
+                        longitude: bits.int(61, 28) as f64 / 600_000.0,
+                        latitude: bits.int(89, 27) as f64 / 600_000.0,
+                        cog: bits.uint(116, 12) as f64 / 10.0,
+                        true_heading: bits.uint(128, 9) as i32,
+                        timestamp: bits.uint(137, 6) as i32,
+                        special_manoeuvre_indicator: bits.uint(143, 2) as i32,
+                        spare: bits.uint(145, 3) as i32,
+                        raim: bits.flag(148),
+                        communication_state: bits.uint(149, 19) as i32,
+                    }));
+            }
+            5 => {
+                let data = ShipStaticData {
+                    message_id,
+                    repeat_indicator,
+                    user_id: mmsi,
+                    valid: true,
+                    ais_version: bits.uint(38, 2) as i32,
+                    imo_number: bits.uint(40, 30) as i32,
+                    call_sign: bits.text(70, 7),
+                    name: bits.text(112, 20),
+                    ship_type: bits.uint(232, 8) as i32,
+                    dimension: bits.dimension(240),
+                    fix_type: bits.uint(270, 4) as i32,
+                    eta: Eta {
+                        month: bits.uint(274, 4) as i32,
+                        day: bits.uint(278, 5) as i32,
+                        hour: bits.uint(283, 5) as i32,
+                        minute: bits.uint(288, 6) as i32,
+                    },
+                    maximum_static_draught: bits.uint(294, 8) as f64 / 10.0,
+                    destination: bits.text(302, 20),
+                    dte: bits.flag(422),
+                };
+                report.name = ais_text(&data.name);
+                report.bodies.push(AISMessageBody::ShipStaticData(data));
+            }
+            18 | 19 => {
+                report.position = bits.position(57, 85);
+                let extended = message_id == 19;
+                report
+                    .bodies
+                    .push(AISMessageBody::ClassBPositionReport(ClassBPositionReport {
+                        message_id,
+                        repeat_indicator,
+                        user_id: mmsi,
+                        valid: true,
+                        sog: bits.uint(46, 10) as f64 / 10.0,
+                        position_accuracy: bits.flag(56),
+                        longitude: bits.int(57, 28) as f64 / 600_000.0,
+                        latitude: bits.int(85, 27) as f64 / 600_000.0,
+                        cog: bits.uint(112, 12) as f64 / 10.0,
+                        true_heading: bits.uint(124, 9) as i32,
+                        timestamp: bits.uint(133, 6) as i32,
+                        assigned_mode: bits.flag(if extended { 307 } else { 146 }),
+                        raim: bits.flag(if extended { 305 } else { 147 }),
+                    }));
+                if extended {
+                    let name = bits.text(143, 20);
+                    report.name = ais_text(&name);
+                    report
+                        .bodies
+                        .push(AISMessageBody::StaticDataReport(StaticDataReport {
+                            message_id,
+                            repeat_indicator,
+                            user_id: mmsi,
+                            valid: true,
+                            part_number: false,
+                            report_a: StaticReportA { valid: true, name },
+                            report_b: StaticReportB {
+                                valid: true,
+                                ship_type: bits.uint(263, 8) as i32,
+                                // not in message 19, the merge keeps the one of part B
+                                call_sign: String::new(),
+                                dimension: bits.dimension(271),
+                                fix_type: bits.uint(301, 4) as i32,
+                            },
+                        }));
+                }
+            }
+            21 => {
+                // the name may continue in up to 14 characters after the fixed fields
+                let extension = (bits.len() - 272) / 6;
+                let aid = AidsToNavigationReport {
+                    message_id,
+                    repeat_indicator,
+                    user_id: mmsi,
+                    valid: true,
+                    aton_type: bits.uint(38, 5) as i32,
+                    name: bits.text(43, 20),
+                    position_accuracy: bits.flag(163),
+                    longitude: bits.int(164, 28) as f64 / 600_000.0,
+                    latitude: bits.int(192, 27) as f64 / 600_000.0,
+                    dimension: bits.dimension(219),
+                    timestamp: bits.uint(253, 6) as i32,
+                    off_position: bits.flag(259),
+                    raim: bits.flag(268),
+                    virtual_aton: bits.flag(269),
+                    assigned_mode: bits.flag(270),
+                    name_extension: bits.text(272, extension),
+                };
+                report.name = ais_text(&(aid.name.clone() + &aid.name_extension));
+                report.position = bits.position(164, 192);
+                report
+                    .bodies
+                    .push(AISMessageBody::AidsToNavigationReport(aid));
+            }
+            24 => {
+                let part_b = bits.uint(38, 2) == 1;
+                let mut data = StaticDataReport {
+                    message_id,
+                    repeat_indicator,
+                    user_id: mmsi,
+                    valid: true,
+                    part_number: part_b,
+                    report_a: StaticReportA::default(),
+                    report_b: StaticReportB::default(),
+                };
+                if part_b {
+                    data.report_b = StaticReportB {
+                        valid: true,
+                        ship_type: bits.uint(40, 8) as i32,
+                        call_sign: bits.text(90, 7),
+                        dimension: bits.dimension(132),
+                        fix_type: 0,
+                    };
+                } else {
+                    data.report_a = StaticReportA {
+                        valid: true,
+                        name: bits.text(40, 20),
+                    };
+                    report.name = ais_text(&data.report_a.name);
+                }
+                report.bodies.push(AISMessageBody::StaticDataReport(data));
+            }
+            27 => {
+                // long range broadcast, position in 1/10 minutes and speed in whole knots
+                let longitude = bits.int(44, 18) as f64 / 600.0;
+                let latitude = bits.int(62, 17) as f64 / 600.0;
+                report.position = (longitude.abs() <= 180.0 && latitude.abs() <= 90.0)
+                    .then_some((longitude, latitude));
+                let sog = bits.uint(79, 6);
+                let cog = bits.uint(85, 9);
+                report
+                    .bodies
+                    .push(AISMessageBody::PositionReport(PositionReport {
+                        message_id,
+                        repeat_indicator,
+                        user_id: mmsi,
+                        valid: true,
+                        navigational_status: bits.uint(40, 4) as i32,
+                        rate_of_turn: -128,
+                        sog: if sog == 63 { 102.3 } else { sog as f64 },
+                        position_accuracy: bits.flag(38),
+                        longitude,
+                        latitude,
+                        cog: if cog == 511 { 360.0 } else { cog as f64 },
+                        true_heading: 511,
+                        timestamp: 60,
+                        special_manoeuvre_indicator: 0,
+                        spare: 0,
+                        raim: bits.flag(39),
+                        communication_state: 0,
+                    }));
+            }
+            _ => unreachable!(),
+        }
+
+        Some(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(lines: &[&str]) -> Option<AisReport> {
+        let mut assembler = Assembler::default();
+        let mut payload = None;
+        for line in lines {
+            payload = assembler.push(Sentence::parse(line)?);
+        }
+        let (payload, fill_bits) = payload?;
+        AisReport::decode(&payload, fill_bits)
+    }
+
+    fn static_data_report(report: AisReport) -> StaticDataReport {
+        report
+            .bodies
+            .into_iter()
+            .find_map(|body| match body {
+                AISMessageBody::StaticDataReport(data) => Some(data),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn checksum() {
+        let line = "!AIVDM,1,1,,A,15RTgt0PAso;90TKcjM8h6g208CQ,0*4A";
+        assert!(Sentence::parse(line).is_some());
+        assert_eq!(Sentence::parse(&line.replace("*4A", "*4B")), None);
+        assert_eq!(Sentence::parse(&line.replace(",A,", ",B,")), None);
+        assert_eq!(
+            Sentence::parse("!AIVDM,1,1,,A,15RTgt0PAso;90TKcjM8h6g208CQ,0"),
+            None
+        );
+    }
+
+    #[test]
+    fn tag_block() {
+        let line = "!AIVDM,1,1,,A,15RTgt0PAso;90TKcjM8h6g208CQ,0*4A";
+        let tagged = format!("\\s:2573345,c:1671620143*0C\\{}\r\n", line);
+        assert_eq!(Sentence::parse(&tagged), Sentence::parse(line));
+        assert_eq!(Sentence::parse(&tagged).unwrap().channel, "A");
+    }
+
+    #[test]
+    fn fragment_assembly() {
+        let first =
+            "!AIVDM,2,1,1,A,55?MbV02;H;s<HtKR20EHE:0@T4@Dn2222222216L961O5Gf0NSQEp6ClRp8,0*1C";
+        let second = "!AIVDM,2,2,1,A,88888888880,2*25";
+        let mut assembler = Assembler::default();
+        assert_eq!(assembler.push(Sentence::parse(first).unwrap()), None);
+        let (payload, fill_bits) = assembler.push(Sentence::parse(second).unwrap()).unwrap();
+        assert_eq!(payload.len(), 71);
+        assert_eq!(fill_bits, 2);
+        assert!(assembler.pending.is_empty());
+
+        // a second fragment without its first is dropped
+        assert_eq!(assembler.push(Sentence::parse(second).unwrap()), None);
+        // so is a first fragment that is followed by a new message with the same id
+        assembler.push(Sentence::parse(first).unwrap());
+        assembler.push(Sentence::parse(first).unwrap());
+        assert!(assembler.push(Sentence::parse(second).unwrap()).is_some());
+    }
+
+    #[test]
+    fn six_bit_fields() {
+        let bits = Bits::unarmor("0W`w", 0).unwrap();
+        assert_eq!(bits.len(), 24);
+        assert_eq!(bits.uint(0, 6), 0);
+        assert_eq!(bits.uint(6, 6), 39);
+        assert_eq!(bits.uint(12, 6), 40);
+        assert_eq!(bits.uint(18, 6), 63);
+        // 63 read as a 6 bit two's complement
+        assert_eq!(bits.int(18, 6), -1);
+        // past the end reads as 0
+        assert_eq!(bits.uint(22, 4), 0b1100);
+
+        assert_eq!(Bits::unarmor("0W", 2).unwrap().len(), 10);
+        assert!(Bits::unarmor("X", 0).is_none());
+        assert!(Bits::unarmor("x", 0).is_none());
+
+        // "A1" in 6-bit ASCII
+        let bits = Bits::unarmor("1i", 0).unwrap();
+        assert_eq!(bits.text(0, 2), "A1");
+    }
+
+    #[test]
+    fn position_report() {
+        let report = decode(&["!AIVDM,1,1,,A,15RTgt0PAso;90TKcjM8h6g208CQ,0*4A"]).unwrap();
+        assert_eq!(report.mmsi, 371798000);
+        let (longitude, latitude) = report.position.unwrap();
+        assert!((longitude + 123.395383).abs() < 1e-6);
+        assert!((latitude - 48.381633).abs() < 1e-6);
+        let Some(AISMessageBody::PositionReport(position)) = report.bodies.first() else {
+            panic!("no position report");
+        };
+        assert_eq!(position.sog, 12.3);
+        assert_eq!(position.cog, 224.0);
+        assert_eq!(position.true_heading, 215);
+    }
+
+    #[test]
+    fn ship_static_data() {
+        let report = decode(&[
+            "!AIVDM,2,1,1,A,55?MbV02;H;s<HtKR20EHE:0@T4@Dn2222222216L961O5Gf0NSQEp6ClRp8,0*1C",
+            "!AIVDM,2,2,1,A,88888888880,2*25",
+        ])
+        .unwrap();
+        assert_eq!(report.mmsi, 351759000);
+        assert_eq!(report.name.as_deref(), Some("EVER DIADEM"));
+        assert_eq!(report.position, None);
+        let Some(AISMessageBody::ShipStaticData(data)) = report.bodies.first() else {
+            panic!("no ship static data");
+        };
+        assert_eq!(ais_text(&data.call_sign).as_deref(), Some("3FOF8"));
+        assert_eq!(ais_text(&data.destination).as_deref(), Some("NEW YORK"));
+        assert_eq!(data.ship_type, 70);
+        assert_eq!(data.maximum_static_draught, 12.2);
+        assert_eq!(data.dimension.a + data.dimension.b, 295);
+    }
+
+    #[test]
+    fn bad_checksum_is_dropped() {
+        assert!(decode(&["!AIVDM,1,1,,A,15RTgt0PAso;90TKcjM8h6g208CR,0*4A"]).is_none());
+    }
+
+    #[test]
+    fn static_data_report_parts() {
+        let part_a = static_data_report(
+            decode(&["!AIVDM,1,1,,A,H42O55i18tMET00000000000000,2*6D"]).unwrap(),
+        );
+        assert!(!part_a.part_number);
+        assert!(part_a.report_a.valid && !part_a.report_b.valid);
+        assert_eq!(ais_text(&part_a.report_a.name).as_deref(), Some("PROGUY"));
+
+        let part_b = static_data_report(
+            decode(&["!AIVDM,1,1,,A,H42O55lti4hhhilD3nink000?050,0*40"]).unwrap(),
+        );
+        assert!(part_b.part_number);
+        assert!(!part_b.report_a.valid && part_b.report_b.valid);
+        assert_eq!(part_b.report_b.ship_type, 60);
+        assert_eq!(
+            ais_text(&part_b.report_b.call_sign).as_deref(),
+            Some("TC6163")
+        );
+
+        let merged = part_a.merge(part_b);
+        assert_eq!(ais_text(&merged.report_a.name).as_deref(), Some("PROGUY"));
+        assert_eq!(
+            ais_text(&merged.report_b.call_sign).as_deref(),
+            Some("TC6163")
+        );
+    }
+
+    #[test]
+    fn extended_class_b_keeps_call_sign() {
+        let part_b = static_data_report(
+            decode(&["!AIVDM,1,1,,A,H42O55lti4hhhilD3nink000?050,0*40"]).unwrap(),
+        );
+        let extended =
+            decode(&["!AIVDM,1,1,,B,C42O55h0=8Q<1P5oGL0p@eg0PTN>bj00000000000000N007P2R0,0*18"])
+                .unwrap();
+        assert_eq!(extended.position, Some((29.0, 41.0)));
+        assert_eq!(extended.name.as_deref(), Some("PROGUY"));
+
+        let merged = part_b.merge(static_data_report(extended));
+        assert_eq!(ais_text(&merged.report_a.name).as_deref(), Some("PROGUY"));
+        assert_eq!(
+            ais_text(&merged.report_b.call_sign).as_deref(),
+            Some("TC6163")
+        );
+    }
+}
@@ -0,0 +1,440 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::time::common_conditions::on_real_timer;
+use bevy_octopus::prelude::*;
+use bevy_tacview::record::{Coords, Property};
+
+use crate::config::Config;
+use crate::network::{log_event, split_lines};
+use crate::opensky::category_tags;
+use crate::track::{TrackKey, TrackPlugin, TrackSource, TrackState, Tracks};
+use crate::units::{FEET_PER_MINUTE, FOOT, KNOT};
+
+const OGN_CHANNEL: ChannelId = ChannelId("OGN");
+
+/// APRS-IS servers drop clients that stay silent for too long
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(240);
+
+/// Reads OGN (FLARM, OGN tracker, PilotAware, ...) beacons from an APRS-IS server.
+pub struct OgnPlugin {
+    /// APRS-IS server, e.g. tcp://aprs.glidernet.org:14580
+    pub address: String,
+    /// callsign to log in with, the passcode -1 gives read-only access
+    pub callsign: String,
+}
+
+impl Plugin for OgnPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(OgnResource {
+            address: self.address.clone(),
+            callsign: self.callsign.clone(),
+            logged_in: None,
+        })
+        .register_type::<OgnAircraft>()
+        .add_systems(Startup, setup)
+        .add_systems(
+            Update,
+            (
+                handle_connect,
+                update_filter.run_if(resource_changed::<Config>),
+                handle_raw_packet,
+                keepalive.run_if(on_real_timer(KEEPALIVE_INTERVAL)),
+            ),
+        )
+        .add_plugins(TrackPlugin::<OgnAircraft>::default());
+    }
+}
+
+#[derive(Resource)]
+pub struct OgnResource {
+    pub address: String,
+    pub callsign: String,
+    /// node of the logged in connection, the filter can only be changed on that one
+    pub logged_in: Option<Entity>,
+}
+
+/// setup the connection to the APRS-IS server
+fn setup(res: Res<OgnResource>, mut commands: Commands) {
+    commands.spawn((OGN_CHANNEL, ConnectTo::new(&res.address)));
+}
+
+/// log in with a range filter for every area of interest
+fn handle_connect(
+    mut res: ResMut<OgnResource>,
+    config: Res<Config>,
+    mut ev_node: EventReader<NetworkNodeEvent>,
+    q_net_node: Query<&NetworkNode>,
+) {
+    for NetworkNodeEvent {
+        node: entity,
+        channel_id,
+        event,
+    } in ev_node.read()
+    {
+        if *channel_id != OGN_CHANNEL {
+            continue;
+        }
+
+        log_event(channel_id, event);
+        match event {
+            NetworkEvent::Connected => match q_net_node.get(*entity) {
+                Ok(node) => {
+                    node.send_text(format!(
+                        "user {} pass -1 vers {} {} filter {}\r\n",
+                        res.callsign,
+                        env!("CARGO_PKG_NAME"),
+                        env!("CARGO_PKG_VERSION"),
+                        area_filter(&config)
+                    ));
+                    res.logged_in = Some(*entity);
+                }
+                Err(e) => error!("no node to log in on {}: {:?}", channel_id, e),
+            },
+            NetworkEvent::Disconnected | NetworkEvent::Error(_) => res.logged_in = None,
+            NetworkEvent::Listen => {}
+        }
+    }
+}
+
+/// APRS-IS takes a new filter without logging in again
+fn update_filter(res: Res<OgnResource>, config: Res<Config>, q_net_node: Query<&NetworkNode>) {
+    let Some(node) = res.logged_in.and_then(|entity| q_net_node.get(entity).ok()) else {
+        return;
+    };
+    info!("update {} filter", OGN_CHANNEL);
+    node.send_text(format!("#filter {}\r\n", area_filter(&config)));
+}
+
+/// APRS-IS area filter, one `a/north/west/south/east` box for every area of interest
+fn area_filter(config: &Config) -> String {
+    config
+        .areas
+        .iter()
+        .map(|area| {
+            format!(
+                "a/{}/{}/{}/{}",
+                area.max_lat, area.min_lon, area.min_lat, area.max_lon
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn keepalive(q_server: Query<(&ChannelId, &NetworkNode)>) {
+    for (channel_id, net_node) in q_server.iter() {
+        if *channel_id == OGN_CHANNEL {
+            net_node.send_text("# keepalive\r\n".to_string());
+        }
+    }
+}
+
+/// OGN device address in upper case hex
+#[derive(Debug, Component, Reflect, Clone, PartialEq, Eq, Hash)]
+pub struct OgnKey(pub String);
+
+impl TrackKey for OgnKey {}
+
+#[derive(Debug, Component, Reflect, Clone, PartialEq)]
+pub struct OgnAircraft {
+    /// device address in upper case hex
+    pub address: String,
+    /// APRS source callsign, e.g. FLRDDA5BA
+    pub callsign: String,
+    /// OGN aircraft type, 1 glider .. 15 static object
+    pub aircraft_type: u8,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// altitude above mean sea level in meters
+    pub altitude: Option<f64>,
+    /// course over ground in degrees clockwise from north
+    pub track: Option<f64>,
+    /// ground speed in m/s
+    pub ground_speed: Option<f64>,
+    /// climb rate in m/s
+    pub climb_rate: Option<f64>,
+}
+
+impl OgnAircraft {
+    /// Parse an APRS position beacon, beacons without an OGN `id` (receivers, weather stations)
+    /// and aircraft that ask not to be tracked are ignored.
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        // server comments and keepalives
+        if line.starts_with('#') {
+            return None;
+        }
+        let (header, body) = line.split_once(':')?;
+        let callsign = header.split('>').next()?.to_string();
+        // position with timestamp: `/HHMMSSh` then `DDMM.mmN` `\` `DDDMM.mmE` `'`
+        if !(body.starts_with('/') || body.starts_with('@')) {
+            return None;
+        }
+        let position = body.get(8..27)?;
+        let mut latitude = parse_coordinate(position.get(0..8)?, 2)?;
+        let mut longitude = parse_coordinate(position.get(9..18)?, 3)?;
+        let mut rest = body.get(27..)?;
+
+        let mut track = None;
+        let mut ground_speed = None;
+        if let Some((course, speed)) = rest
+            .get(0..7)
+            .and_then(|ext| ext.split_once('/'))
+            .filter(|(course, speed)| course.len() == 3 && speed.len() == 3)
+        {
+            if let (Ok(course), Ok(speed)) = (course.parse::<f64>(), speed.parse::<f64>()) {
+                // 000 means the course is unknown, north is sent as 360
+                track = (course > 0.0).then_some(course % 360.0);
+                ground_speed = Some(speed * KNOT);
+                rest = &rest[7..];
+            }
+        }
+        let altitude = rest
+            .strip_prefix("/A=")
+            .and_then(|alt| alt.get(0..6))
+            .and_then(|alt| alt.parse::<f64>().ok())
+            .map(|feet| feet * FOOT);
+
+        let mut id = None;
+        let mut climb_rate = None;
+        for token in rest.split_whitespace() {
+            if let Some(enhancement) = token.strip_prefix("!W").and_then(|t| t.strip_suffix('!')) {
+                // third decimal of the latitude and longitude minutes
+                let mut digits = enhancement.chars().filter_map(|c| c.to_digit(10));
+                if let (Some(lat), Some(lon)) = (digits.next(), digits.next()) {
+                    latitude += latitude.signum() * lat as f64 / 60_000.0;
+                    longitude += longitude.signum() * lon as f64 / 60_000.0;
+                }
+            } else if let Some(value) = token
+                .strip_prefix("id")
+                .filter(|t| t.len() == 8 && t.is_ascii())
+            {
+                let flags = u8::from_str_radix(&value[0..2], 16).ok()?;
+                u32::from_str_radix(&value[2..], 16).ok()?;
+                id = Some((flags, value[2..].to_string()));
+            } else if let Some(fpm) = token.strip_suffix("fpm") {
+                climb_rate = fpm.parse::<f64>().ok().map(|fpm| fpm * FEET_PER_MINUTE);
+            }
+        }
+
+        // the id byte is STttttaa: stealth, no-tracking, aircraft type and address type
+        let (flags, address) = id?;
+        if flags & 0x40 != 0 {
+            return None;
+        }
+
+        Some(Self {
+            address,
+            callsign,
+            aircraft_type: (flags >> 2) & 0x0F,
+            latitude,
+            longitude,
+            altitude,
+            track,
+            ground_speed,
+            climb_rate,
+        })
+    }
+}
+
+/// `DDMM.mmN` or `DDDMM.mmE` to decimal degrees, south and west are negative
+fn parse_coordinate(text: &str, degree_digits: usize) -> Option<f64> {
+    let degrees = text.get(..degree_digits)?.parse::<f64>().ok()?;
+    let minutes = text
+        .get(degree_digits..text.len() - 1)?
+        .parse::<f64>()
+        .ok()?;
+    let value = degrees + minutes / 60.0;
+    match text.chars().last()? {
+        'N' | 'E' => Some(value),
+        'S' | 'W' => Some(-value),
+        _ => None,
+    }
+}
+
+fn handle_raw_packet(
+    q_server: Query<(&ChannelId, &NetworkNode)>,
+    mut tracks: Tracks<OgnAircraft>,
+    mut buffer: Local<Vec<u8>>,
+) {
+    let mut beacons = vec![];
+    for (channel_id, net_node) in q_server.iter() {
+        if *channel_id != OGN_CHANNEL {
+            continue;
+        }
+        while let Ok(Some(packet)) = net_node.recv_message_channel.receiver.try_recv() {
+            for line in split_lines(&mut buffer, &packet.bytes) {
+                if let Some(aircraft) = OgnAircraft::parse(&line) {
+                    trace!("ogn beacon: {:?}", aircraft);
+                    beacons.push(aircraft);
+                }
+            }
+        }
+    }
+    tracks.update(beacons);
+}
+
+/// every beacon carries the whole state
+impl TrackState for OgnAircraft {
+    type Key = OgnKey;
+    type Message = OgnAircraft;
+
+    fn key(aircraft: &OgnAircraft) -> OgnKey {
+        OgnKey(aircraft.address.clone())
+    }
+
+    fn new(aircraft: OgnAircraft) -> Option<Self> {
+        Some(aircraft)
+    }
+
+    fn merge(&mut self, aircraft: OgnAircraft) {
+        *self = aircraft;
+    }
+}
+
+impl TrackSource for OgnAircraft {
+    type Key = OgnKey;
+    type Data = &'static OgnAircraft;
+    type Changed = Changed<OgnAircraft>;
+
+    const TIMEOUT: Option<Duration> = Some(Duration::from_secs(120));
+
+    fn coords(aircraft: &&OgnAircraft) -> Coords {
+        Coords {
+            longitude: Some(aircraft.longitude),
+            latitude: Some(aircraft.latitude),
+            altitude: aircraft.altitude,
+            u: None,
+            v: None,
+            roll: Some(0.0),
+            pitch: Some(0.0),
+            yaw: aircraft.track,
+            heading: None,
+        }
+    }
+
+    fn props(aircraft: &&OgnAircraft) -> Vec<Property> {
+        let (name, category) = aircraft_type(aircraft.aircraft_type);
+        let mut list = vec![
+            Property::Name(name.to_string()),
+            Property::CallSign(aircraft.callsign.clone()),
+            Property::Type(category_tags(Some(category))),
+        ];
+
+        if let Some(ground_speed) = aircraft.ground_speed {
+            list.push(Property::Unknown(
+                "GroundSpeed".to_string(),
+                format!("{:.1}", ground_speed),
+            ));
+        }
+        if let Some(climb_rate) = aircraft.climb_rate {
+            list.push(Property::Unknown(
+                "VerticalSpeed".to_string(),
+                format!("{:.1}", climb_rate),
+            ));
+        }
+
+        list
+    }
+}
+
+/// Name and ADS-B emitter category of the OGN aircraft type, so OGN aircraft get the same
+/// [`category_tags`] as the ADS-B ones. The generic names match no Tacview 3D model, the shape
+/// shown follows the tags.
+fn aircraft_type(aircraft_type: u8) -> (&'static str, u32) {
+    match aircraft_type {
+        1 => ("Glider", 9),
+        2 => ("Tow Plane", 2),
+        3 => ("Helicopter", 8),
+        4 => ("Skydiver", 11),
+        5 => ("Drop Plane", 2),
+        6 => ("Hang Glider", 12),
+        7 => ("Paraglider", 12),
+        8 => ("Powered Aircraft", 2),
+        // mostly business jets
+        9 => ("Jet", 3),
+        11 => ("Balloon", 10),
+        12 => ("Airship", 10),
+        13 => ("UAV", 14),
+        14 => ("Ground Support", 17),
+        15 => ("Static Object", 18),
+        _ => ("Unknown", 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Area;
+
+    const BEACON: &str = "FLRDDA5BA>APRS,qAS,LFMX:/160829h4415.41N/00600.03E'342/049/A=005524 \
+                          !W52! id06DDA5BA -454fpm -1.1rot 8.8dB 0e +51.2kHz gps4x5";
+
+    #[test]
+    fn beacon() {
+        let aircraft = OgnAircraft::parse(BEACON).unwrap();
+        assert_eq!(aircraft.address, "DDA5BA");
+        assert_eq!(aircraft.callsign, "FLRDDA5BA");
+        assert_eq!(aircraft.aircraft_type, 1);
+        // 44°15.415'N 6°00.032'E with the !W52! enhancement
+        assert!((aircraft.latitude - (44.0 + 15.415 / 60.0)).abs() < 1e-9);
+        assert!((aircraft.longitude - (6.0 + 0.032 / 60.0)).abs() < 1e-9);
+        assert!((aircraft.altitude.unwrap() - 5524.0 * FOOT).abs() < 1e-9);
+        assert_eq!(aircraft.track, Some(342.0));
+        assert!((aircraft.ground_speed.unwrap() - 49.0 * KNOT).abs() < 1e-9);
+        assert!((aircraft.climb_rate.unwrap() + 454.0 * FEET_PER_MINUTE).abs() < 1e-9);
+    }
+
+    #[test]
+    fn southern_and_western_coordinates() {
+        let beacon = BEACON.replace("4415.41N/00600.03E", "4415.41S/00600.03W");
+        let aircraft = OgnAircraft::parse(&beacon).unwrap();
+        assert!((aircraft.latitude + (44.0 + 15.415 / 60.0)).abs() < 1e-9);
+        assert!((aircraft.longitude + (6.0 + 0.032 / 60.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ignored_beacons() {
+        // no-tracking flag
+        assert!(OgnAircraft::parse(&BEACON.replace("id06", "id46")).is_none());
+        // receiver status without an id
+        assert!(OgnAircraft::parse(
+            "LFMX>OGNSDR,TCPIP*,qAC,GLIDERN2:/160830h4415.45NI00600.12E&/A=001700 v0.2.8"
+        )
+        .is_none());
+        assert!(OgnAircraft::parse("# aprsc 2.1.4-g408ed49").is_none());
+        // a multi-byte character in the id must not panic
+        assert!(OgnAircraft::parse(&BEACON.replace("id06DDA5BA", "id0é5BA45")).is_none());
+    }
+
+    #[test]
+    fn tags_by_aircraft_type() {
+        let glider = category_tags(Some(aircraft_type(1).1));
+        let paraglider = category_tags(Some(aircraft_type(7).1));
+        let balloon = category_tags(Some(aircraft_type(11).1));
+        let powered = category_tags(Some(aircraft_type(8).1));
+        assert_ne!(glider, paraglider);
+        assert_ne!(glider, balloon);
+        assert_ne!(glider, powered);
+        assert_ne!(paraglider, balloon);
+    }
+
+    #[test]
+    fn filter_box_per_area() {
+        let area = |name: &str, min_lat, max_lat, min_lon, max_lon| Area {
+            name: name.to_string(),
+            min_lat,
+            max_lat,
+            min_lon,
+            max_lon,
+        };
+        let config = Config {
+            areas: vec![
+                area("alps", 45.5, 47.5, 6.0, 10.5),
+                area("sisteron", 44.0, 44.5, 5.5, 6.5),
+            ],
+            ..default()
+        };
+        assert_eq!(area_filter(&config), "a/47.5/6/45.5/10.5 a/44.5/5.5/44/6.5");
+    }
+}
@@ -1,52 +1,230 @@
-use std::collections::HashSet;
-use std::time::Duration;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
 
 use base64::prelude::BASE64_STANDARD;
 use base64::Engine;
-use bevy::prelude::*;
 use bevy::time::common_conditions::on_real_timer;
-use bevy_activation::ActiveState;
+use bevy::{ecs::query::QueryData, prelude::*};
 use bevy_http_client::{
     HttpClient, HttpClientPlugin, HttpRequest, HttpResponse, HttpResponseError,
 };
 use bevy_inspector_egui::quick::ResourceInspectorPlugin;
-use bevy_tacview::record::{Coords, Property, PropertyList, Tag};
+use bevy_tacview::record::{Coords, Property, Tag};
 use bevy_tacview::systems::ObjectNeedSync;
+use chrono::{DateTime, Utc};
+use serde::de::IgnoredAny;
 use serde::Deserialize;
-use url::Url;
+use url::{form_urlencoded, Url};
+
+use crate::config::{Area, Config, OpenSkyAuth, OpenSkyConfig};
+use crate::network::Fetcher;
+use crate::track::{TrackIndex, TrackKey, TrackPlugin, TrackSource, TrackState, Tracks};
+
+const API_URL: &str = "https://opensky-network.org/api";
+
+/// renew the OAuth2 token this long before it expires
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+/// wait this long before retrying a failed or unanswered token request
+const TOKEN_RETRY: Duration = Duration::from_secs(30);
+/// first wait after a rejected or failed request, doubled with every further failure
+const BACKOFF_BASE: Duration = Duration::from_secs(10);
+/// longest wait between two attempts
+const BACKOFF_MAX: Duration = Duration::from_secs(15 * 60);
+/// window of the track and flight request caps
+const REQUEST_CAP_WINDOW: Duration = Duration::from_secs(3600);
+/// time between two requests of the arrivals and departures of an airport
+const AIRPORT_INTERVAL: Duration = Duration::from_secs(3600);
+/// how far back flights are looked up, OpenSky limits the interval of the aircraft endpoint to two days
+const FLIGHT_LOOKBACK: i64 = 2 * 24 * 3600;
 
-#[derive(Default)]
 pub struct OpenSkyPlugin {
-    pub username: Option<String>,
-    pub password: Option<String>,
+    pub credentials: OpenSkyCredentials,
+    /// time between two state requests
+    pub interval: Duration,
+    /// request the track of every new aircraft, at most this many per hour, `None` to disable
+    pub track_backfill: Option<u32>,
+    /// request the last flight of every new aircraft, at most this many per hour, `None` to disable
+    pub flight_lookup: Option<u32>,
+    /// ICAO codes of the airports whose arrivals and departures are requested
+    pub airports: Vec<String>,
+    /// request the states seen by these own receivers from `/states/own` instead of the areas,
+    /// an empty list means all own receivers
+    pub own_serials: Option<Vec<u64>>,
+}
+
+impl Default for OpenSkyPlugin {
+    fn default() -> Self {
+        Self {
+            credentials: OpenSkyCredentials::Anonymous,
+            interval: Duration::from_secs(10),
+            track_backfill: None,
+            flight_lookup: None,
+            airports: Vec::new(),
+            own_serials: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum OpenSkyCredentials {
+    #[default]
+    Anonymous,
+    /// legacy username and password login
+    Basic { username: String, password: String },
+    /// OAuth2 client credentials flow
+    OAuth2 {
+        client_id: String,
+        client_secret: String,
+        token_url: String,
+    },
+}
+
+impl OpenSkyCredentials {
+    /// Read the credentials of the configured auth mode from the environment, falls back to
+    /// anonymous access if they are missing.
+    pub fn from_env(config: &OpenSkyConfig) -> Self {
+        match config.auth {
+            OpenSkyAuth::Basic => {
+                match (
+                    std::env::var("OPENSKY_USERNAME").ok(),
+                    std::env::var("OPENSKY_PASSWORD").ok(),
+                ) {
+                    (Some(username), Some(password)) => Self::Basic { username, password },
+                    _ => Self::Anonymous,
+                }
+            }
+            OpenSkyAuth::OAuth2 => {
+                match (
+                    std::env::var("OPENSKY_CLIENT_ID").ok(),
+                    std::env::var("OPENSKY_CLIENT_SECRET").ok(),
+                ) {
+                    (Some(client_id), Some(client_secret)) => Self::OAuth2 {
+                        client_id,
+                        client_secret,
+                        token_url: config.token_url.clone(),
+                    },
+                    _ => {
+                        warn!("OpenSky client credentials not set, using anonymous access");
+                        Self::Anonymous
+                    }
+                }
+            }
+        }
+    }
 }
 
 impl Plugin for OpenSkyPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(HttpClientPlugin)
-            .insert_resource(OpenSkyResource::new(&self.username, &self.password))
+        if !app.is_plugin_added::<HttpClientPlugin>() {
+            app.add_plugins(HttpClientPlugin)
+                .add_systems(Update, handle_error);
+        }
+        if self.own_serials.is_some() && matches!(self.credentials, OpenSkyCredentials::Anonymous) {
+            warn!("/states/own needs OpenSky credentials, every request will fail");
+        }
+        let opensky_res = OpenSkyResource::new(&self.credentials, self.own_serials.clone());
+        app.insert_resource(opensky_res)
             .init_resource::<OpenSKyController>()
+            .init_resource::<OpenSkyRateLimit>()
             .add_event::<StateRequest>()
             .register_type::<StateVector>()
             .register_type::<OpenSKyController>()
-            .add_plugins(ResourceInspectorPlugin::<OpenSKyController>::default())
+            .register_type::<OpenSkyRateLimit>()
             .add_systems(
                 Update,
                 (
-                    refresh_states.run_if(on_real_timer(Duration::from_secs(10))),
+                    refresh_states.run_if(on_real_timer(self.interval)),
                     get_all_states,
+                    refresh_token.run_if(resource_exists::<OAuthToken>),
+                    handle_token_response.run_if(resource_exists::<OAuthToken>),
                     handle_state_response,
-                    handle_error,
-                    watch_added,
-                    watch_changed,
+                    handle_rate_limit,
                 ),
-            );
+            )
+            .add_plugins(TrackPlugin::<Aircraft>::default());
+
+        if let Some(requests_per_hour) = self.track_backfill {
+            app.insert_resource(TrackBackfill::new(requests_per_hour))
+                .register_type::<TrailPoint>()
+                .add_systems(
+                    Update,
+                    (
+                        queue_tracks,
+                        request_tracks,
+                        handle_track_response,
+                        remove_trails,
+                    )
+                        .run_if(resource_exists::<TrackBackfill>),
+                )
+                .add_plugins(TrackPlugin::<TrailPoint>::default());
+        }
+
+        if self.flight_lookup.is_some() || !self.airports.is_empty() {
+            app.insert_resource(FlightLookup::new(self.flight_lookup, self.airports.clone()))
+                .register_type::<FlightInfo>()
+                .add_systems(
+                    Update,
+                    (
+                        queue_flights,
+                        request_flights,
+                        request_airports,
+                        handle_flight_response,
+                    )
+                        .run_if(resource_exists::<FlightLookup>),
+                );
+        }
+
+        if let OpenSkyCredentials::OAuth2 {
+            client_id,
+            client_secret,
+            token_url,
+        } = &self.credentials
+        {
+            app.insert_resource(OAuthToken {
+                client_id: client_id.clone(),
+                client_secret: client_secret.clone(),
+                token_url: token_url.clone(),
+                expires_at: None,
+                requested_at: None,
+            })
+            .init_resource::<Fetcher<OAuthToken>>();
+        }
+
+        if app.is_plugin_added::<WindowPlugin>() {
+            app.add_plugins((
+                ResourceInspectorPlugin::<OpenSKyController>::default(),
+                ResourceInspectorPlugin::<OpenSkyRateLimit>::default(),
+            ));
+        }
     }
 }
 
 #[derive(Resource, Debug)]
 pub struct OpenSkyResource {
+    /// value of the `Authorization` header, `None` for anonymous access
     pub auth: Option<String>,
+    /// serials of the own receivers to request the states of, `None` to request the areas
+    pub own_serials: Option<Vec<u64>>,
+}
+
+/// State of the OAuth2 client credentials flow, the current bearer token lives in
+/// [`OpenSkyResource::auth`].
+#[derive(Resource, Debug)]
+pub struct OAuthToken {
+    pub client_id: String,
+    pub client_secret: String,
+    pub token_url: String,
+    /// when the current token expires, `None` before the first token was received
+    pub expires_at: Option<Instant>,
+    /// when the pending token request was sent
+    pub requested_at: Option<Instant>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    /// lifetime of the token in seconds
+    expires_in: u64,
 }
 
 #[derive(Resource, Reflect, Default)]
@@ -54,14 +232,62 @@ pub struct OpenSKyController {
     pub open: bool,
 }
 
+/// API credits left and the backoff after rejected or failed requests.
+#[derive(Resource, Reflect, Default, Debug)]
+pub struct OpenSkyRateLimit {
+    /// credits left today, from the `X-Rate-Limit-Remaining` header of the last response
+    pub remaining_credits: Option<u64>,
+    /// credits one refresh of all areas costs
+    pub credits_per_refresh: u32,
+    /// credits the refreshes cost per day at the configured interval
+    pub credits_per_day: u64,
+    /// failed requests in a row
+    pub failures: u32,
+    /// seconds of the current backoff
+    pub backoff: u64,
+    /// no requests before this time
+    #[reflect(ignore)]
+    pub retry_at: Option<Instant>,
+}
+
+impl OpenSkyRateLimit {
+    /// Whether requests may be sent, `false` while backing off.
+    pub fn ready(&self) -> bool {
+        match self.retry_at {
+            Some(retry_at) => Instant::now() >= retry_at,
+            None => true,
+        }
+    }
+
+    fn back_off(&mut self, retry_after: Option<Duration>) {
+        self.failures += 1;
+        let backoff = retry_after.unwrap_or_else(|| {
+            BACKOFF_BASE
+                .saturating_mul(2u32.saturating_pow(self.failures - 1))
+                .min(BACKOFF_MAX)
+        });
+        self.backoff = backoff.as_secs();
+        self.retry_at = Some(Instant::now() + backoff);
+    }
+}
+
+/// ICAO24 address of an aircraft seen by OpenSky
+#[derive(Debug, Component, Reflect, Clone, PartialEq, Eq, Hash)]
+pub struct OpenSkyKey(pub String);
+
+impl TrackKey for OpenSkyKey {}
+
 impl OpenSkyResource {
-    pub fn new(username: &Option<String>, password: &Option<String>) -> Self {
-        let auth = if let (Some(username), Some(password)) = (username, password) {
-            Some(BASE64_STANDARD.encode(&format!("{}:{}", username, password)))
-        } else {
-            None
+    pub fn new(credentials: &OpenSkyCredentials, own_serials: Option<Vec<u64>>) -> Self {
+        let auth = match credentials {
+            OpenSkyCredentials::Basic { username, password } => Some(format!(
+                "Basic {}",
+                BASE64_STANDARD.encode(format!("{}:{}", username, password))
+            )),
+            // the bearer token is filled in once the token endpoint answered
+            OpenSkyCredentials::Anonymous | OpenSkyCredentials::OAuth2 { .. } => None,
         };
-        Self { auth }
+        Self { auth, own_serials }
     }
 }
 
@@ -74,6 +300,9 @@ pub struct StateRequest {
     pub bounding_box: Option<BoundingBox>,
     /// if request the  state vector category, set to 1
     pub extended: Option<u8>,
+    /// Request the states seen by own receivers from `/states/own`, filtered by these serials if not
+    /// empty. The bounding box is not supported by this endpoint.
+    pub own_serials: Option<Vec<u64>>,
 }
 
 #[derive(Debug, Default)]
@@ -88,6 +317,30 @@ pub struct BoundingBox {
     pub max_lon: f64,
 }
 
+impl BoundingBox {
+    /// Credits a state request of this box costs, by its area in square degrees.
+    pub fn credit_cost(&self) -> u32 {
+        let area = (self.max_lat - self.min_lat).abs() * (self.max_lon - self.min_lon).abs();
+        match area {
+            a if a <= 25.0 => 1,
+            a if a <= 100.0 => 2,
+            a if a <= 400.0 => 3,
+            _ => 4,
+        }
+    }
+}
+
+impl From<&Area> for BoundingBox {
+    fn from(area: &Area) -> Self {
+        BoundingBox {
+            min_lat: area.min_lat,
+            max_lat: area.max_lat,
+            min_lon: area.min_lon,
+            max_lon: area.max_lon,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct StateResponse {
     pub time: u64,
@@ -113,10 +366,12 @@ pub struct InnerStateVector(
     Option<String>,
     bool,
     u8,
-    // Option<i32>
+    /// only present in extended mode
+    #[serde(default)]
+    Option<u32>,
 );
 
-#[derive(Debug, Component, Reflect)]
+#[derive(Debug, Component, Reflect, Clone)]
 pub struct StateVector {
     /// Unique ICAO 24-bit address of the transponder in hex string representation.
     pub icao24: String,
@@ -160,7 +415,7 @@ pub struct StateVector {
     ///
     /// * 3 = FLARM
     pub position_source: u8,
-    ////Aircraft category.
+    /// Aircraft category, only requested in extended mode.
     ///
     /// * 0 = No information at all
     ///
@@ -212,6 +467,24 @@ impl PartialEq for StateVector {
     }
 }
 
+/// every state carries the whole state vector
+impl TrackState for StateVector {
+    type Key = OpenSkyKey;
+    type Message = StateVector;
+
+    fn key(state: &StateVector) -> OpenSkyKey {
+        OpenSkyKey(state.icao24.clone())
+    }
+
+    fn new(state: StateVector) -> Option<Self> {
+        Some(state)
+    }
+
+    fn merge(&mut self, state: StateVector) {
+        *self = state;
+    }
+}
+
 impl From<InnerStateVector> for StateVector {
     fn from(inner: InnerStateVector) -> Self {
         StateVector {
@@ -232,43 +505,96 @@ impl From<InnerStateVector> for StateVector {
             squawk: inner.14,
             spi: inner.15,
             position_source: inner.16,
-            category: None,
+            category: inner.17,
         }
     }
 }
 
+/// request the states of every configured area, or of the own receivers
 fn refresh_states(
     mut state_req: EventWriter<StateRequest>,
     open_sky_controller: Res<OpenSKyController>,
+    opensky_res: Res<OpenSkyResource>,
+    mut rate_limit: ResMut<OpenSkyRateLimit>,
+    config: Res<Config>,
 ) {
-    if open_sky_controller.open {
-        state_req.send(StateRequest {
-            bounding_box: Some(BoundingBox {
-                min_lat: 3.2063329870791444,
-                max_lat: 29.477861195816843,
-                min_lon: 97.4267578125,
-                max_lon: 141.48193359375003,
-            }),
-
+    if !open_sky_controller.open {
+        return;
+    }
+    if !rate_limit.ready() {
+        debug!(
+            "skip state request, backing off for {}s",
+            rate_limit.backoff
+        );
+        return;
+    }
+    let requests = if let Some(serials) = opensky_res.own_serials.as_ref() {
+        vec![StateRequest {
+            extended: Some(1),
+            own_serials: Some(serials.clone()),
             ..default()
-        });
+        }]
+    } else {
+        config
+            .areas
+            .iter()
+            .map(|area| StateRequest {
+                bounding_box: Some(BoundingBox::from(area)),
+                extended: Some(1),
+                ..default()
+            })
+            .collect()
+    };
+
+    // states seen by own receivers are free
+    let credits = requests
+        .iter()
+        .filter(|req| req.own_serials.is_none())
+        .map(|req| {
+            req.bounding_box
+                .as_ref()
+                .map_or(4, BoundingBox::credit_cost)
+        })
+        .sum::<u32>();
+    if rate_limit.credits_per_refresh != credits {
+        let credits_per_day = credits as u64 * 24 * 3600 / config.opensky.interval.max(1);
+        info!(
+            "OpenSky state requests cost {} credits, {} per day",
+            credits, credits_per_day
+        );
+        rate_limit.credits_per_refresh = credits;
+        rate_limit.credits_per_day = credits_per_day;
     }
+    state_req.send_batch(requests);
 }
 
 fn get_all_states(
     mut events: EventReader<StateRequest>,
     mut state_req: EventWriter<HttpRequest>,
     opensky_res: Res<OpenSkyResource>,
+    oauth_token: Option<Res<OAuthToken>>,
 ) {
     for req in events.read() {
+        if opensky_res.auth.is_none() && oauth_token.is_some() {
+            debug!("skip state request, waiting for the OAuth2 token");
+            continue;
+        }
         debug!("request state: {:?}", req);
-        let api_url = "https://opensky-network.org/api/states/all";
-        let mut url = Url::parse(api_url).unwrap();
+        let endpoint = if req.own_serials.is_some() {
+            "own"
+        } else {
+            "all"
+        };
+        let mut url = Url::parse(&format!("{}/states/{}", API_URL, endpoint)).unwrap();
+        for serial in req.own_serials.iter().flatten() {
+            url.query_pairs_mut()
+                .append_pair("serials", &serial.to_string());
+        }
         if let Some(time) = req.time {
             url.query_pairs_mut().append_pair("time", &time.to_string());
         }
         if let Some(ico24) = req.icao24.as_ref() {
-            url.query_pairs_mut().append_pair("icao24", &ico24);
+            url.query_pairs_mut().append_pair("icao24", ico24);
         }
         if let Some(bbox) = req.bounding_box.as_ref() {
             url.query_pairs_mut()
@@ -277,134 +603,1138 @@ fn get_all_states(
                 .append_pair("lamax", &bbox.max_lat.to_string())
                 .append_pair("lomax", &bbox.max_lon.to_string());
         }
+        if let Some(extended) = req.extended {
+            url.query_pairs_mut()
+                .append_pair("extended", &extended.to_string());
+        }
 
-        let req = if let Some(auth) = opensky_res.auth.as_ref() {
-            HttpClient::new()
-                .headers(&[
-                    ("Content-Type", "application/json"),
-                    ("Accept", "*/*"),
-                    ("Authorization", format!("Basic {}", auth).as_str()),
-                ])
-                .get(url)
-                .build()
-        } else {
-            HttpClient::new().get(url).build()
+        state_req.send(api_request(url, &opensky_res));
+    }
+}
+
+/// GET request to the API, logged in if there are credentials
+fn api_request(url: Url, opensky_res: &OpenSkyResource) -> HttpRequest {
+    if let Some(auth) = opensky_res.auth.as_ref() {
+        HttpClient::new()
+            .headers(&[
+                ("Content-Type", "application/json"),
+                ("Accept", "*/*"),
+                ("Authorization", auth.as_str()),
+            ])
+            .get(url)
+            .build()
+    } else {
+        HttpClient::new().get(url).build()
+    }
+}
+
+/// Request a new OAuth2 token before the current one expires.
+fn refresh_token(mut token: ResMut<OAuthToken>, fetcher: Res<Fetcher<OAuthToken>>) {
+    let now = Instant::now();
+    if let Some(expires_at) = token.expires_at {
+        if expires_at > now + TOKEN_REFRESH_MARGIN {
+            return;
+        }
+    }
+    if let Some(requested_at) = token.requested_at {
+        if now < requested_at + TOKEN_RETRY {
+            return;
+        }
+    }
+
+    debug!("request OAuth2 token from {}", token.token_url);
+    let body = form_urlencoded::Serializer::new(String::new())
+        .append_pair("grant_type", "client_credentials")
+        .append_pair("client_id", &token.client_id)
+        .append_pair("client_secret", &token.client_secret)
+        .finish();
+    let mut request = ehttp::Request::post(&token.token_url, body.into_bytes());
+    request.headers = ehttp::Headers::new(&[
+        ("Accept", "application/json"),
+        ("Content-Type", "application/x-www-form-urlencoded"),
+    ]);
+    fetcher.fetch(request);
+    token.requested_at = Some(now);
+}
+
+/// Take the token from the answers of the token endpoint, the token requests are the only ones
+/// sent through `Fetcher<OAuthToken>`.
+fn handle_token_response(
+    fetcher: Res<Fetcher<OAuthToken>>,
+    mut token: ResMut<OAuthToken>,
+    mut opensky_res: ResMut<OpenSkyResource>,
+) {
+    for result in fetcher.responses() {
+        // keep `requested_at` on failures so the next attempt waits for the retry delay
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                error!("OAuth2 token request failed: {}", e);
+                continue;
+            }
         };
-        state_req.send(req);
+        match serde_json::from_slice::<TokenResponse>(&response.bytes) {
+            Ok(resp_json) if response.ok => {
+                debug!("OAuth2 token valid for {}s", resp_json.expires_in);
+                opensky_res.auth = Some(format!("Bearer {}", resp_json.access_token));
+                token.expires_at = Some(Instant::now() + Duration::from_secs(resp_json.expires_in));
+                token.requested_at = None;
+            }
+            _ => {
+                error!(
+                    "OAuth2 token request failed: {} {:?}",
+                    response.status,
+                    response.text()
+                );
+            }
+        }
+    }
+}
+
+/// Track the credits left and back off when OpenSky rejects requests or fails, at most once per
+/// refresh as the requests of all areas fail alike.
+fn handle_rate_limit(
+    mut ev_response: EventReader<HttpResponse>,
+    mut rate_limit: ResMut<OpenSkyRateLimit>,
+) {
+    for response in ev_response.read() {
+        if !response.url.starts_with(API_URL) {
+            continue;
+        }
+        if let Some(remaining) = response
+            .headers
+            .get("x-rate-limit-remaining")
+            .and_then(|remaining| remaining.trim().parse().ok())
+        {
+            rate_limit.remaining_credits = Some(remaining);
+        }
+        // nothing is requested while backing off, the answers still coming in belong to the
+        // refresh that failed already and are counted with it
+        if !rate_limit.ready() {
+            continue;
+        }
+
+        match response.status {
+            429 => {
+                let retry_after = response
+                    .headers
+                    .get("x-rate-limit-retry-after-seconds")
+                    .and_then(|seconds| seconds.trim().parse().ok())
+                    .map(Duration::from_secs);
+                rate_limit.back_off(retry_after);
+                warn!("OpenSky credits used up, retry in {}s", rate_limit.backoff);
+            }
+            500..=599 => {
+                rate_limit.back_off(None);
+                warn!(
+                    "OpenSky request failed with {}, retry in {}s",
+                    response.status, rate_limit.backoff
+                );
+            }
+            _ if response.ok => {
+                rate_limit.failures = 0;
+                rate_limit.backoff = 0;
+                rate_limit.retry_at = None;
+            }
+            _ => {}
+        }
     }
 }
 
 /// Handle the response from the OpenSky API
 /// and spawn new entities or update existing ones.
-#[allow(unused_assignments)]
-fn handle_state_response(
+pub(crate) fn handle_state_response(
     mut ev_response: EventReader<HttpResponse>,
-    mut query: Query<&mut StateVector>,
-    mut commands: Commands,
+    mut tracks: Tracks<StateVector>,
 ) {
+    // overlapping areas report the same aircraft several times, the latest state wins
+    let mut states = vec![];
     for response in ev_response.read() {
+        if !response.url.starts_with(&format!("{}/states/", API_URL)) {
+            continue;
+        }
         match response.json::<StateResponse>() {
-            Ok(resp_json) => {
-                let states = resp_json
-                    .states
-                    .into_iter()
-                    .map(StateVector::from)
-                    .collect::<Vec<_>>();
-                trace!("Response: {:?}", states);
-
-                let mut new_batches = vec![];
-                'a: for new_state in states {
-                    let mut not_find = true;
-                    for mut state in query.iter_mut() {
-                        if state.icao24 == new_state.icao24 {
-                            state.set_if_neq(new_state);
-                            not_find = false;
-                            continue 'a;
-                        }
-                    }
-
-                    if not_find {
-                        new_batches.push(new_state);
-                    }
-                }
-                commands.spawn_batch(new_batches);
+            Ok(resp_json) if response.ok => {
+                trace!("Response: {:?}", resp_json.states);
+                states.extend(resp_json.states.into_iter().map(StateVector::from));
             }
-            Err(_e) => {
-                error!("Error: {:?}", response.text());
-                return;
+            _ => {
+                error!(
+                    "state request failed: {} {:?}",
+                    response.status,
+                    response.text()
+                );
             }
         }
     }
+
+    tracks.update(states);
 }
 
-fn handle_error(mut ev_error: EventReader<HttpResponseError>) {
-    for error in ev_error.read() {
-        error!("Error: {:?}", error);
+/// Sliding window limit of the requests per hour.
+#[derive(Debug)]
+pub struct RequestCap {
+    /// when the requests of the last hour were sent
+    pub sent: VecDeque<Instant>,
+    pub requests_per_hour: u32,
+}
+
+impl RequestCap {
+    pub fn new(requests_per_hour: u32) -> Self {
+        Self {
+            sent: VecDeque::new(),
+            requests_per_hour,
+        }
+    }
+
+    /// Whether another request fits into the last hour.
+    pub fn available(&mut self) -> bool {
+        let now = Instant::now();
+        while self
+            .sent
+            .front()
+            .is_some_and(|sent| now.duration_since(*sent) > REQUEST_CAP_WINDOW)
+        {
+            self.sent.pop_front();
+        }
+        self.sent.len() < self.requests_per_hour as usize
+    }
+
+    pub fn count(&mut self) {
+        self.sent.push_back(Instant::now());
+    }
+}
+
+/// Pending track requests.
+#[derive(Resource, Debug)]
+pub struct TrackBackfill {
+    /// ICAO24 addresses of new aircraft waiting for their track
+    pub queue: VecDeque<String>,
+    pub cap: RequestCap,
+}
+
+impl TrackBackfill {
+    pub fn new(requests_per_hour: u32) -> Self {
+        Self {
+            queue: VecDeque::new(),
+            cap: RequestCap::new(requests_per_hour),
+        }
     }
 }
 
-fn watch_added(query: Query<(Entity, &StateVector), Added<StateVector>>, mut commands: Commands) {
-    for (e, state) in query.iter() {
-        debug!("Added: {:?}", state);
-        let coord = to_coords(state);
-        let props = to_props(state);
+#[derive(Debug, Deserialize)]
+struct TrackResponse {
+    icao24: String,
+    callsign: Option<String>,
+    path: Vec<Waypoint>,
+}
 
-        commands.entity(e).insert((
-            coord,
-            PropertyList(props),
-            ObjectNeedSync::Spawn,
-            ActiveState::new(Duration::from_secs(20)),
-        ));
+/// time, latitude, longitude, barometric altitude, true track and the unused on ground flag
+#[derive(Debug, Deserialize)]
+struct Waypoint(
+    u64,
+    Option<f64>,
+    Option<f64>,
+    Option<f64>,
+    Option<f64>,
+    IgnoredAny,
+);
+
+/// A past position of an aircraft from its OpenSky track, shown as a waypoint next to the live
+/// aircraft. The telemetry stream cannot go back in time, so the time of the waypoint is its label.
+#[derive(Debug, Component, Reflect, Clone, PartialEq)]
+pub struct TrailPoint {
+    pub icao24: String,
+    pub callsign: Option<String>,
+    /// Unix timestamp in seconds
+    pub time: u64,
+    pub longitude: f64,
+    pub latitude: f64,
+    /// barometric altitude in meters
+    pub baro_altitude: Option<f64>,
+    pub true_track: Option<f64>,
+}
+
+/// ICAO24 address of the aircraft and time of a trail point
+#[derive(Debug, Component, Reflect, Clone, PartialEq, Eq, Hash)]
+pub struct TrailKey {
+    pub icao24: String,
+    pub time: u64,
+}
+
+impl TrackKey for TrailKey {}
+
+/// Trail points do not change, a waypoint seen twice is the same one.
+impl TrackState for TrailPoint {
+    type Key = TrailKey;
+    type Message = TrailPoint;
+
+    fn key(point: &TrailPoint) -> TrailKey {
+        TrailKey {
+            icao24: point.icao24.clone(),
+            time: point.time,
+        }
+    }
+
+    fn new(point: TrailPoint) -> Option<Self> {
+        Some(point)
+    }
+
+    fn merge(&mut self, point: TrailPoint) {
+        *self = point;
+    }
+}
+
+fn queue_tracks(
+    query: Query<&StateVector, Added<StateVector>>,
+    mut backfill: ResMut<TrackBackfill>,
+) {
+    for state in query.iter() {
+        backfill.queue.push_back(state.icao24.clone());
     }
 }
 
-fn watch_changed(
-    mut query: Query<
-        (
-            Entity,
-            &StateVector,
-            &mut Coords,
-            &mut PropertyList,
-            &mut ActiveState,
-        ),
-        Changed<StateVector>,
-    >,
+/// Request the queued tracks as long as the hourly cap allows.
+fn request_tracks(
+    mut backfill: ResMut<TrackBackfill>,
+    mut http_req: EventWriter<HttpRequest>,
+    icao24_index: Res<TrackIndex<OpenSkyKey>>,
+    opensky_res: Res<OpenSkyResource>,
+    oauth_token: Option<Res<OAuthToken>>,
+    rate_limit: Res<OpenSkyRateLimit>,
+) {
+    // drop the aircraft that are gone, the queue only grows while capped
+    backfill
+        .queue
+        .retain(|icao24| icao24_index.contains_key(&OpenSkyKey(icao24.clone())));
+    if (opensky_res.auth.is_none() && oauth_token.is_some()) || !rate_limit.ready() {
+        return;
+    }
+    while backfill.cap.available() {
+        let Some(icao24) = backfill.queue.pop_front() else {
+            break;
+        };
+        debug!("request track: {}", icao24);
+        let mut url = Url::parse(&format!("{}/tracks/all", API_URL)).unwrap();
+        url.query_pairs_mut()
+            .append_pair("icao24", &icao24)
+            .append_pair("time", "0");
+        http_req.send(api_request(url, &opensky_res));
+        backfill.cap.count();
+    }
+}
+
+/// Spawn the waypoints flown before the aircraft showed up as its trail.
+fn handle_track_response(
+    mut ev_response: EventReader<HttpResponse>,
+    icao24_index: Res<TrackIndex<OpenSkyKey>>,
+    query: Query<&StateVector>,
+    mut trails: Tracks<TrailPoint>,
+) {
+    for response in ev_response.read() {
+        if !response.url.starts_with(&format!("{}/tracks/", API_URL)) {
+            continue;
+        }
+        let track = match response.json::<TrackResponse>() {
+            Ok(track) if response.ok => track,
+            // 404 if OpenSky has no track for the aircraft
+            _ => {
+                debug!("no track: {} {:?}", response.status, response.text());
+                continue;
+            }
+        };
+        let Some(state) = icao24_index
+            .get(&OpenSkyKey(track.icao24.clone()))
+            .and_then(|entity| query.get(*entity).ok())
+        else {
+            continue;
+        };
+        let trail = trail_points(state.time_position.unwrap_or(state.last_contact), track);
+        debug!("{} trail points for {}", trail.len(), state.icao24);
+        trails.update(trail);
+    }
+}
+
+/// The waypoints with a position before the first live state.
+fn trail_points(live_since: u64, track: TrackResponse) -> Vec<TrailPoint> {
+    let callsign = track
+        .callsign
+        .map(|callsign| callsign.trim().to_string())
+        .filter(|callsign| !callsign.is_empty());
+    track
+        .path
+        .into_iter()
+        .filter(|waypoint| waypoint.0 < live_since)
+        .filter_map(
+            |Waypoint(time, latitude, longitude, baro_altitude, true_track, _)| {
+                Some(TrailPoint {
+                    icao24: track.icao24.clone(),
+                    callsign: callsign.clone(),
+                    time,
+                    longitude: longitude?,
+                    latitude: latitude?,
+                    baro_altitude,
+                    true_track,
+                })
+            },
+        )
+        .collect()
+}
+
+/// Remove the trail together with its aircraft.
+fn remove_trails(
+    q_aircraft: Query<(&OpenSkyKey, &ObjectNeedSync), Changed<ObjectNeedSync>>,
+    trail_index: Res<TrackIndex<TrailKey>>,
+    mut commands: Commands,
+) {
+    for (key, _) in q_aircraft
+        .iter()
+        .filter(|(_, sync)| matches!(sync, ObjectNeedSync::Destroy))
+    {
+        for (_, entity) in trail_index
+            .iter()
+            .filter(|(trail_key, _)| trail_key.icao24 == key.0)
+        {
+            commands.entity(*entity).insert(ObjectNeedSync::Destroy);
+        }
+    }
+}
+
+impl TrackSource for TrailPoint {
+    type Key = TrailKey;
+    type Data = &'static TrailPoint;
+    type Changed = Changed<TrailPoint>;
+
+    /// removed together with the aircraft
+    const TIMEOUT: Option<Duration> = None;
+
+    fn coords(point: &&TrailPoint) -> Coords {
+        Coords {
+            longitude: Some(point.longitude),
+            latitude: Some(point.latitude),
+            altitude: point.baro_altitude,
+            u: None,
+            v: None,
+            roll: None,
+            pitch: None,
+            yaw: point.true_track,
+            heading: None,
+        }
+    }
+
+    fn props(point: &&TrailPoint) -> Vec<Property> {
+        let time = DateTime::from_timestamp(point.time as i64, 0).unwrap_or_default();
+        let label = point.callsign.as_ref().unwrap_or(&point.icao24);
+        vec![
+            Property::Name(format!("{} {}", label, time.format("%H:%M:%S"))),
+            Property::ICAO24(point.icao24.clone()),
+            Property::Type(HashSet::from_iter([
+                Tag::Navaid,
+                Tag::Static,
+                Tag::Waypoint,
+            ])),
+            Property::Unknown("Time".to_string(), time.to_rfc3339()),
+        ]
+    }
+}
+
+/// Pending flight requests and the latest flight of every aircraft seen in a response.
+#[derive(Resource, Debug)]
+pub struct FlightLookup {
+    /// ICAO24 addresses of new aircraft waiting for their flight, only used with a cap
+    pub queue: VecDeque<String>,
+    /// `None` if only the airports are requested
+    pub cap: Option<RequestCap>,
+    pub airports: Vec<String>,
+    /// when the arrivals and departures were last requested
+    pub airports_requested_at: Option<Instant>,
+    /// latest flight by ICAO24 address
+    pub flights: HashMap<String, FlightInfo>,
+}
+
+impl FlightLookup {
+    pub fn new(requests_per_hour: Option<u32>, airports: Vec<String>) -> Self {
+        Self {
+            queue: VecDeque::new(),
+            cap: requests_per_hour.map(RequestCap::new),
+            airports,
+            airports_requested_at: None,
+            flights: HashMap::new(),
+        }
+    }
+}
+
+/// One flight of the `/flights` endpoints, the airports are estimated by OpenSky.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FlightJson {
+    icao24: String,
+    first_seen: u64,
+    est_departure_airport: Option<String>,
+    last_seen: u64,
+    est_arrival_airport: Option<String>,
+}
+
+/// Estimated route of the last flight OpenSky processed for an aircraft.
+#[derive(Debug, Component, Reflect, Clone, PartialEq)]
+pub struct FlightInfo {
+    /// ICAO code of the departure airport
+    pub departure: Option<String>,
+    /// ICAO code of the arrival airport
+    pub arrival: Option<String>,
+    /// Unix timestamp (seconds) of the first message of the flight
+    pub first_seen: u64,
+    /// Unix timestamp (seconds) of the last message of the flight
+    pub last_seen: u64,
+}
+
+impl From<FlightJson> for FlightInfo {
+    fn from(json: FlightJson) -> Self {
+        Self {
+            departure: json.est_departure_airport,
+            arrival: json.est_arrival_airport,
+            first_seen: json.first_seen,
+            last_seen: json.last_seen,
+        }
+    }
+}
+
+/// Attach known flights to new aircraft and queue the lookup of the others.
+fn queue_flights(
+    query: Query<(Entity, &StateVector), Added<StateVector>>,
+    mut lookup: ResMut<FlightLookup>,
     mut commands: Commands,
 ) {
-    for (entity, state, mut coords, mut props_list, mut active_state) in query.iter_mut() {
-        trace!("Changed: {:?} after {}", state.icao24, state.last_contact);
-        coords.set_if_neq(to_coords(state));
-        props_list.set_if_neq(PropertyList(to_props(state)));
-        active_state.toggle();
-        commands.entity(entity).insert(ObjectNeedSync::Update);
+    for (entity, state) in query.iter() {
+        if let Some(flight) = lookup.flights.get(&state.icao24) {
+            commands.entity(entity).insert(flight.clone());
+        } else if lookup.cap.is_some() {
+            lookup.queue.push_back(state.icao24.clone());
+        }
     }
 }
 
-fn to_coords(state: &StateVector) -> Coords {
-    Coords {
-        longitude: state.longitude,
-        latitude: state.latitude,
-        altitude: state.baro_altitude,
-        u: None,
-        v: None,
-        roll: Some(0.0),
-        pitch: Some(0.0),
-        yaw: state.true_track,
-        heading: None,
+/// Request the flights of the queued aircraft as long as the hourly cap allows.
+fn request_flights(
+    mut lookup: ResMut<FlightLookup>,
+    mut http_req: EventWriter<HttpRequest>,
+    icao24_index: Res<TrackIndex<OpenSkyKey>>,
+    opensky_res: Res<OpenSkyResource>,
+    oauth_token: Option<Res<OAuthToken>>,
+    rate_limit: Res<OpenSkyRateLimit>,
+) {
+    if (opensky_res.auth.is_none() && oauth_token.is_some()) || !rate_limit.ready() {
+        return;
+    }
+    let lookup = lookup.as_mut();
+    let Some(cap) = lookup.cap.as_mut() else {
+        return;
+    };
+    let end = Utc::now().timestamp();
+    while cap.available() {
+        let Some(icao24) = lookup.queue.pop_front() else {
+            break;
+        };
+        // gone before it was its turn, or an airport response already had its flight
+        if !icao24_index.contains_key(&OpenSkyKey(icao24.clone()))
+            || lookup.flights.contains_key(&icao24)
+        {
+            continue;
+        }
+        debug!("request flights: {}", icao24);
+        let mut url = Url::parse(&format!("{}/flights/aircraft", API_URL)).unwrap();
+        url.query_pairs_mut()
+            .append_pair("icao24", &icao24)
+            .append_pair("begin", &(end - FLIGHT_LOOKBACK).to_string())
+            .append_pair("end", &end.to_string());
+        http_req.send(api_request(url, &opensky_res));
+        cap.count();
     }
 }
 
-fn to_props(state: &StateVector) -> Vec<Property> {
-    let mut list = vec![
-        Property::Name(state.icao24.clone()),
-        Property::ICAO24(state.icao24.clone()),
-        Property::Country(state.origin_country.clone()),
-        Property::Type(HashSet::from_iter([Tag::FixedWing])),
-    ];
+/// Request the arrivals and departures of every configured airport.
+fn request_airports(
+    mut lookup: ResMut<FlightLookup>,
+    mut http_req: EventWriter<HttpRequest>,
+    opensky_res: Res<OpenSkyResource>,
+    oauth_token: Option<Res<OAuthToken>>,
+    rate_limit: Res<OpenSkyRateLimit>,
+) {
+    if lookup.airports.is_empty()
+        || lookup
+            .airports_requested_at
+            .is_some_and(|requested_at| requested_at.elapsed() < AIRPORT_INTERVAL)
+        || (opensky_res.auth.is_none() && oauth_token.is_some())
+        || !rate_limit.ready()
+    {
+        return;
+    }
+    let end = Utc::now().timestamp();
+    for airport in lookup.airports.iter() {
+        for direction in ["arrival", "departure"] {
+            debug!("request {}s: {}", direction, airport);
+            let mut url = Url::parse(&format!("{}/flights/{}", API_URL, direction)).unwrap();
+            url.query_pairs_mut()
+                .append_pair("airport", airport)
+                .append_pair("begin", &(end - FLIGHT_LOOKBACK).to_string())
+                .append_pair("end", &end.to_string());
+            http_req.send(api_request(url, &opensky_res));
+        }
+    }
+    lookup.airports_requested_at = Some(Instant::now());
+}
+
+/// Keep the latest flight of every aircraft and attach it to the aircraft if it is tracked.
+fn handle_flight_response(
+    mut ev_response: EventReader<HttpResponse>,
+    mut lookup: ResMut<FlightLookup>,
+    icao24_index: Res<TrackIndex<OpenSkyKey>>,
+    mut query: Query<Option<&mut FlightInfo>, With<StateVector>>,
+    mut commands: Commands,
+) {
+    let mut updated = HashSet::new();
+    for response in ev_response.read() {
+        if !response.url.starts_with(&format!("{}/flights/", API_URL)) {
+            continue;
+        }
+        let flights = match response.json::<Vec<FlightJson>>() {
+            Ok(flights) if response.ok => flights,
+            // 404 if there are no flights in the interval
+            _ => {
+                debug!("no flights: {} {:?}", response.status, response.text());
+                continue;
+            }
+        };
+        for flight in flights {
+            let icao24 = flight.icao24.clone();
+            let flight = FlightInfo::from(flight);
+            if lookup
+                .flights
+                .get(&icao24)
+                .is_some_and(|known| known.last_seen >= flight.last_seen)
+            {
+                continue;
+            }
+            lookup.flights.insert(icao24.clone(), flight);
+            updated.insert(icao24);
+        }
+    }
+    if updated.is_empty() {
+        return;
+    }
+
+    for icao24 in updated {
+        let Some(entity) = icao24_index.get(&OpenSkyKey(icao24.clone())) else {
+            continue;
+        };
+        let flight = lookup.flights[&icao24].clone();
+        match query.get_mut(*entity) {
+            Ok(Some(mut old)) => {
+                old.set_if_neq(flight);
+            }
+            Ok(None) => {
+                commands.entity(*entity).insert(flight);
+            }
+            Err(_) => {}
+        }
+    }
+    // flights older than the lookback would not be requested again either
+    let oldest = (Utc::now().timestamp() - FLIGHT_LOOKBACK) as u64;
+    lookup
+        .flights
+        .retain(|_, flight| flight.last_seen >= oldest);
+}
 
-    if let Some(call_sign) = state.callsign.as_ref() {
-        list.push(Property::CallSign(call_sign.clone()));
+/// log failed requests, registered by whichever plugin adds the HTTP client
+pub(crate) fn handle_error(mut ev_error: EventReader<HttpResponseError>) {
+    for error in ev_error.read() {
+        error!("Error: {:?}", error);
     }
+}
+
+#[derive(QueryData)]
+struct Aircraft {
+    state: &'static StateVector,
+    flight: Option<&'static FlightInfo>,
+}
+
+/// The flight is looked up after the aircraft showed up, so either may be the one that changed.
+impl TrackSource for Aircraft {
+    type Key = OpenSkyKey;
+    type Data = Aircraft;
+    type Changed = Or<(Changed<StateVector>, Changed<FlightInfo>)>;
 
-    list
+    const TIMEOUT: Option<Duration> = Some(Duration::from_secs(20));
+
+    fn coords(aircraft: &AircraftItem) -> Coords {
+        let state = aircraft.state;
+        Coords {
+            longitude: state.longitude,
+            latitude: state.latitude,
+            altitude: state.baro_altitude,
+            u: None,
+            v: None,
+            roll: Some(0.0),
+            pitch: Some(0.0),
+            yaw: state.true_track,
+            heading: None,
+        }
+    }
+
+    fn props(aircraft: &AircraftItem) -> Vec<Property> {
+        let state = aircraft.state;
+        let mut list = vec![
+            Property::Name(state.icao24.clone()),
+            Property::ICAO24(state.icao24.clone()),
+            Property::Country(state.origin_country.clone()),
+            Property::Type(category_tags(state.category)),
+        ];
+
+        if let Some(call_sign) = state.callsign.as_ref() {
+            list.push(Property::CallSign(call_sign.clone()));
+        }
+
+        if let Some(sensors) = state.sensors.as_ref().filter(|sensors| !sensors.is_empty()) {
+            list.push(Property::Unknown(
+                "Sensors".to_string(),
+                sensors
+                    .iter()
+                    .map(u64::to_string)
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ));
+        }
+
+        if let Some(category) = category_name(state.category) {
+            list.push(Property::Unknown(
+                "Category".to_string(),
+                category.to_string(),
+            ));
+        }
+
+        if let Some(flight) = aircraft.flight {
+            if let Some(departure) = flight.departure.as_ref() {
+                list.push(Property::Unknown(
+                    "Departure".to_string(),
+                    departure.clone(),
+                ));
+            }
+            if let Some(arrival) = flight.arrival.as_ref() {
+                list.push(Property::Unknown("Arrival".to_string(), arrival.clone()));
+            }
+            for (name, time) in [
+                ("FirstSeen", flight.first_seen),
+                ("LastSeen", flight.last_seen),
+            ] {
+                if let Some(time) = DateTime::from_timestamp(time as i64, 0) {
+                    list.push(Property::Unknown(name.to_string(), time.to_rfc3339()));
+                }
+            }
+        }
+
+        list
+    }
+}
+
+/// Map the ADS-B emitter category to the object tags of the ACMI documentation: a class, a size
+/// attribute and a basic type. Tacview has no glider, balloon, ultralight or UAV type, these stay
+/// `Air` objects told apart by their size and basic type.
+pub(crate) fn category_tags(category: Option<u32>) -> HashSet<Tag> {
+    let tags = match category.unwrap_or(0) {
+        2 => vec![Tag::Air, Tag::FixedWing, Tag::Light],
+        3 => vec![Tag::Air, Tag::FixedWing, Tag::Medium],
+        4..=6 => vec![Tag::Air, Tag::FixedWing, Tag::Heavy],
+        // helicopter
+        8 => vec![Tag::Air, Tag::Rotorcraft],
+        // glider / sailplane, a plane smaller than the light ones
+        9 => vec![Tag::Air, Tag::FixedWing, Tag::Minor],
+        // lighter-than-air, no basic type as it has neither wings nor rotors
+        10 => vec![Tag::Air, Tag::Light],
+        // the documented parachutist
+        11 => vec![Tag::Air, Tag::Light, Tag::Human, Tag::Parachutist],
+        // ultralight / hang-glider / paraglider, the smallest objects, a wing that is not fixed
+        12 => vec![Tag::Air, Tag::Minor],
+        // unmanned aerial vehicle, mostly small multirotors
+        14 => vec![Tag::Air, Tag::Rotorcraft, Tag::Minor],
+        // emergency and service vehicles, the documented ground vehicle
+        16 | 17 => vec![Tag::Ground, Tag::Vehicle],
+        // point, cluster and line obstacles like masts, wind farms and power lines
+        18..=20 => vec![Tag::Ground, Tag::Static, Tag::Building],
+        // the documented plane
+        _ => vec![Tag::Air, Tag::FixedWing],
+    };
+    HashSet::from_iter(tags)
+}
+
+/// human readable ADS-B emitter category, `None` if the transponder does not report one
+fn category_name(category: Option<u32>) -> Option<&'static str> {
+    let name = match category? {
+        2 => "Light",
+        3 => "Small",
+        4 => "Large",
+        5 => "High Vortex Large",
+        6 => "Heavy",
+        7 => "High Performance",
+        8 => "Rotorcraft",
+        9 => "Glider",
+        10 => "Lighter-than-air",
+        11 => "Parachutist",
+        12 => "Ultralight",
+        14 => "UAV",
+        15 => "Space",
+        16 => "Emergency Vehicle",
+        17 => "Service Vehicle",
+        18 => "Point Obstacle",
+        19 => "Cluster Obstacle",
+        20 => "Line Obstacle",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Entry points of the benchmarks in `benches/`, which only see the public API.
+#[cfg(feature = "bench")]
+pub mod bench {
+    use super::*;
+
+    /// App that only runs [`handle_state_response`] on the `HttpResponse` events sent to it.
+    pub fn state_app() -> App {
+        let mut app = App::new();
+        app.init_resource::<TrackIndex<OpenSkyKey>>()
+            .add_event::<HttpResponse>()
+            .add_systems(Update, handle_state_response);
+        app
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use bevy_tacview::record::PropertyList;
+
+    use crate::network::mock::{http_server, response};
+
+    use super::*;
+
+    const TOKEN_JSON: &str =
+        r#"{"access_token": "abc", "expires_in": 1800, "token_type": "Bearer"}"#;
+
+    fn token_app(token_url: String) -> App {
+        let mut app = App::new();
+        app.insert_resource(OpenSkyResource {
+            auth: None,
+            own_serials: None,
+        })
+        .insert_resource(OAuthToken {
+            client_id: "client".to_string(),
+            client_secret: "secret".to_string(),
+            token_url,
+            expires_at: None,
+            requested_at: None,
+        })
+        .init_resource::<Fetcher<OAuthToken>>()
+        .add_systems(Update, (refresh_token, handle_token_response));
+        app
+    }
+
+    /// update until the token endpoint answered and the answer was handled
+    fn wait_for_token(app: &mut App) {
+        let started = Instant::now();
+        while app.world.resource::<OAuthToken>().requested_at.is_some() {
+            assert!(started.elapsed() < Duration::from_secs(5), "no token");
+            std::thread::sleep(Duration::from_millis(10));
+            app.update();
+        }
+    }
+
+    #[test]
+    fn token_response() {
+        let url = http_server(|request| {
+            assert!(request.starts_with("POST /token "), "{request}");
+            response("200 OK", "", TOKEN_JSON)
+        });
+        let mut app = token_app(format!("{url}/token"));
+        app.update();
+        wait_for_token(&mut app);
+
+        assert_eq!(
+            app.world.resource::<OpenSkyResource>().auth.as_deref(),
+            Some("Bearer abc")
+        );
+        let token = app.world.resource::<OAuthToken>();
+        assert!(token.expires_at.unwrap() > Instant::now() + Duration::from_secs(1700));
+    }
+
+    #[test]
+    fn redirected_token_response() {
+        let url = http_server(|request| {
+            if request.starts_with("POST /token ") {
+                response("303 See Other", "Location: /realms/token\r\n", "")
+            } else {
+                response("200 OK", "", TOKEN_JSON)
+            }
+        });
+        let mut app = token_app(format!("{url}/token"));
+        app.update();
+        wait_for_token(&mut app);
+        assert_eq!(
+            app.world.resource::<OpenSkyResource>().auth.as_deref(),
+            Some("Bearer abc")
+        );
+    }
+
+    #[test]
+    fn failed_token_request() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let url = http_server(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            response(
+                "401 Unauthorized",
+                "",
+                r#"{"error": "unauthorized_client"}"#,
+            )
+        });
+        let mut app = token_app(format!("{url}/token"));
+        app.update();
+        // give the answer time to arrive and be handled
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_millis(300) {
+            std::thread::sleep(Duration::from_millis(10));
+            app.update();
+        }
+
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert!(app.world.resource::<OpenSkyResource>().auth.is_none());
+        // waits for the retry delay
+        assert!(app.world.resource::<OAuthToken>().requested_at.is_some());
+    }
+
+    #[test]
+    fn refresh_token_before_expiry() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let url = http_server(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            response("200 OK", "", TOKEN_JSON)
+        });
+        let mut app = token_app(format!("{url}/token"));
+        app.world.resource_mut::<OpenSkyResource>().auth = Some("Bearer old".to_string());
+        app.world.resource_mut::<OAuthToken>().expires_at =
+            Some(Instant::now() + TOKEN_REFRESH_MARGIN * 2);
+
+        // still valid for longer than the margin
+        app.update();
+        assert!(app.world.resource::<OAuthToken>().requested_at.is_none());
+
+        app.world.resource_mut::<OAuthToken>().expires_at =
+            Some(Instant::now() + TOKEN_REFRESH_MARGIN / 2);
+        app.update();
+        assert!(app.world.resource::<OAuthToken>().requested_at.is_some());
+        wait_for_token(&mut app);
+
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            app.world.resource::<OpenSkyResource>().auth.as_deref(),
+            Some("Bearer abc")
+        );
+        let token = app.world.resource::<OAuthToken>();
+        assert!(token.expires_at.unwrap() > Instant::now() + Duration::from_secs(1700));
+    }
+
+    fn api_response(status: u16) -> HttpResponse {
+        HttpResponse(ehttp::Response {
+            url: format!("{}/states/all", API_URL),
+            ok: (200..300).contains(&status),
+            status,
+            status_text: String::new(),
+            headers: ehttp::Headers::new(&[]),
+            bytes: vec![],
+        })
+    }
+
+    #[test]
+    fn one_backoff_per_refresh() {
+        let mut app = App::new();
+        app.init_resource::<OpenSkyRateLimit>()
+            .add_event::<HttpResponse>()
+            .add_systems(Update, handle_rate_limit);
+
+        // every area of the refresh failed, some answers come in a frame later
+        app.world
+            .send_event_batch([api_response(503), api_response(503)]);
+        app.update();
+        app.world.send_event(api_response(503));
+        app.update();
+        let rate_limit = app.world.resource::<OpenSkyRateLimit>();
+        assert_eq!(rate_limit.failures, 1);
+        assert_eq!(rate_limit.backoff, BACKOFF_BASE.as_secs());
+
+        // the next refresh after the backoff fails too
+        app.world.resource_mut::<OpenSkyRateLimit>().retry_at = Some(Instant::now());
+        app.world
+            .send_event_batch([api_response(503), api_response(503)]);
+        app.update();
+        let rate_limit = app.world.resource::<OpenSkyRateLimit>();
+        assert_eq!(rate_limit.failures, 2);
+        assert_eq!(rate_limit.backoff, BACKOFF_BASE.as_secs() * 2);
+
+        app.world.resource_mut::<OpenSkyRateLimit>().retry_at = Some(Instant::now());
+        app.world.send_event(api_response(200));
+        app.update();
+        let rate_limit = app.world.resource::<OpenSkyRateLimit>();
+        assert_eq!(rate_limit.failures, 0);
+        assert!(rate_limit.ready());
+    }
+
+    #[test]
+    fn category_tags_are_distinct() {
+        // light, glider, lighter-than-air, ultralight and UAV
+        let categories = [2, 9, 10, 12, 14];
+        for (i, a) in categories.iter().enumerate() {
+            for b in &categories[i + 1..] {
+                assert_ne!(
+                    category_tags(Some(*a)),
+                    category_tags(Some(*b)),
+                    "{a} and {b}"
+                );
+            }
+        }
+    }
+
+    fn state(icao24: &str, last_contact: u64) -> StateVector {
+        let inner: InnerStateVector = serde_json::from_value(serde_json::json!([
+            icao24,
+            "CPA123  ",
+            "Hong Kong",
+            last_contact,
+            last_contact,
+            114.0,
+            22.3,
+            1000.0,
+            false,
+            200.0,
+            90.0,
+            0.0,
+            null,
+            1100.0,
+            null,
+            false,
+            0,
+            4
+        ]))
+        .unwrap();
+        inner.into()
+    }
+
+    fn track_response(track: serde_json::Value) -> HttpResponse {
+        HttpResponse(ehttp::Response {
+            url: format!("{}/tracks/all?icao24=780a3e&time=0", API_URL),
+            ok: true,
+            status: 200,
+            status_text: String::new(),
+            headers: ehttp::Headers::new(&[("content-type", "application/json")]),
+            bytes: track.to_string().into_bytes(),
+        })
+    }
+
+    fn trail_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(TrackPlugin::<Aircraft>::default())
+            .add_plugins(TrackPlugin::<TrailPoint>::default())
+            .add_event::<HttpResponse>()
+            .add_systems(Update, (handle_track_response, remove_trails));
+        let aircraft = app
+            .world
+            .spawn((OpenSkyKey("780a3e".to_string()), state("780a3e", 1_300)))
+            .id();
+        app.world
+            .resource_mut::<TrackIndex<OpenSkyKey>>()
+            .insert(OpenSkyKey("780a3e".to_string()), aircraft);
+        app
+    }
+
+    #[test]
+    fn trail_before_first_live_state() {
+        let mut app = trail_app();
+        app.world.send_event(track_response(serde_json::json!({
+            "icao24": "780a3e",
+            "callsign": "CPA123  ",
+            "startTime": 900,
+            "endTime": 1_300,
+            "path": [
+                [900, 22.0, 113.0, 0.0, 45.0, false],
+                [1_200, null, null, null, null, false],
+                [1_250, 22.2, 113.8, null, null, false],
+                [1_300, 22.3, 114.0, 1000.0, 90.0, false]
+            ]
+        })));
+        app.update();
+        app.update();
+
+        // without position and live waypoints are left out
+        let index = app.world.resource::<TrackIndex<TrailKey>>();
+        assert_eq!(index.len(), 2);
+        let entity = index[&TrailKey {
+            icao24: "780a3e".to_string(),
+            time: 900,
+        }];
+        let point = app.world.get::<TrailPoint>(entity).unwrap();
+        assert_eq!(point.callsign.as_deref(), Some("CPA123"));
+        assert_eq!(point.longitude, 113.0);
+        assert_eq!(point.baro_altitude, Some(0.0));
+        // synced like any other object
+        assert!(matches!(
+            app.world.get::<ObjectNeedSync>(entity),
+            Some(ObjectNeedSync::Spawn)
+        ));
+        let props = app.world.get::<PropertyList>(entity).unwrap();
+        assert!(props
+            .0
+            .iter()
+            .any(|prop| matches!(prop, Property::Name(name) if name == "CPA123 00:15:00")));
+    }
+
+    #[test]
+    fn trail_is_removed_with_aircraft() {
+        let mut app = trail_app();
+        app.world.send_event(track_response(serde_json::json!({
+            "icao24": "780a3e",
+            "callsign": null,
+            "path": [[900, 22.0, 113.0, 0.0, 45.0, false]]
+        })));
+        app.update();
+        app.update();
+        let point = *app
+            .world
+            .resource::<TrackIndex<TrailKey>>()
+            .values()
+            .next()
+            .unwrap();
+        app.world.entity_mut(point).remove::<ObjectNeedSync>();
+
+        let aircraft =
+            app.world.resource::<TrackIndex<OpenSkyKey>>()[&OpenSkyKey("780a3e".to_string())];
+        app.world
+            .entity_mut(aircraft)
+            .insert(ObjectNeedSync::Destroy);
+        app.update();
+        assert!(matches!(
+            app.world.get::<ObjectNeedSync>(point),
+            Some(ObjectNeedSync::Destroy)
+        ));
+    }
+
+    #[test]
+    fn no_trail_without_waypoints() {
+        let track: TrackResponse = serde_json::from_value(serde_json::json!({
+            "icao24": "780a3e",
+            "callsign": null,
+            "path": [[1_300, 22.3, 114.0, 1000.0, 90.0, false]]
+        }))
+        .unwrap();
+        assert!(trail_points(1_300, track).is_empty());
+    }
 }
@@ -0,0 +1,253 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::time::common_conditions::on_real_timer;
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+
+use crate::adsb::{AdsbAircraft, AdsbMessage, AdsbPlugin};
+use crate::modes::emitter_category;
+use crate::network::Fetcher;
+use crate::track::Tracks;
+use crate::units::{FEET_PER_MINUTE, FOOT, KNOT};
+
+/// Polls the `aircraft.json` of a readsb / tar1090 instance or a community aggregator.
+pub struct ReadsbPlugin {
+    /// e.g. http://127.0.0.1/tar1090/data/aircraft.json
+    pub url: String,
+    /// time between two requests
+    pub interval: Duration,
+}
+
+impl Plugin for ReadsbPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<AdsbPlugin>() {
+            app.add_plugins(AdsbPlugin);
+        }
+        app.insert_resource(ReadsbResource {
+            url: self.url.clone(),
+        })
+        .init_resource::<Fetcher<ReadsbResource>>()
+        .add_systems(
+            Update,
+            (
+                request_aircraft.run_if(on_real_timer(self.interval)),
+                handle_response,
+            ),
+        );
+    }
+}
+
+#[derive(Resource)]
+pub struct ReadsbResource {
+    pub url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AircraftResponse {
+    aircraft: Vec<AircraftJson>,
+}
+
+/// One entry of `aircraft.json`, fields the receiver has no data for are left out.
+#[derive(Debug, Deserialize)]
+struct AircraftJson {
+    /// ICAO 24-bit address in hex, prefixed with `~` for non-ICAO (TIS-B) addresses, the prefix
+    /// is kept so they cannot take the place of an aircraft with the same ICAO address
+    hex: String,
+    flight: Option<String>,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    /// barometric altitude in feet or "ground"
+    alt_baro: Option<BaroAltitude>,
+    /// geometric altitude in feet
+    alt_geom: Option<f64>,
+    /// ground speed in knots
+    gs: Option<f64>,
+    track: Option<f64>,
+    /// barometric vertical rate in feet per minute
+    baro_rate: Option<f64>,
+    squawk: Option<String>,
+    /// emitter category, A0 to D7
+    category: Option<String>,
+    /// emergency status, "none" if there is none
+    emergency: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum BaroAltitude {
+    Feet(f64),
+    /// the only text sent is "ground"
+    Ground(IgnoredAny),
+}
+
+impl From<AircraftJson> for AdsbMessage {
+    fn from(json: AircraftJson) -> Self {
+        let (baro_altitude, on_ground) = match json.alt_baro {
+            Some(BaroAltitude::Feet(feet)) => (Some(feet * FOOT), Some(false)),
+            Some(BaroAltitude::Ground(_)) => (None, Some(true)),
+            None => (None, None),
+        };
+        Self {
+            icao24: json.hex.to_lowercase(),
+            callsign: json
+                .flight
+                .map(|flight| flight.trim().to_string())
+                .filter(|flight| !flight.is_empty()),
+            latitude: json.lat,
+            longitude: json.lon,
+            baro_altitude,
+            geo_altitude: json.alt_geom.map(|feet| feet * FOOT),
+            on_ground,
+            ground_speed: json.gs.map(|knots| knots * KNOT),
+            track: json.track,
+            vertical_rate: json.baro_rate.map(|fpm| fpm * FEET_PER_MINUTE),
+            squawk: json.squawk,
+            category: json.category.as_deref().and_then(parse_category),
+            emergency: json.emergency,
+        }
+    }
+}
+
+/// `A0`..`D7` to the OpenSky category numbering, set A to D are sent with type codes 4 to 1.
+fn parse_category(category: &str) -> Option<u32> {
+    let mut chars = category.chars();
+    let tc = match chars.next()? {
+        'A' => 4,
+        'B' => 3,
+        'C' => 2,
+        'D' => 1,
+        _ => return None,
+    };
+    let ca = chars.next()?.to_digit(8)?;
+    Some(emitter_category(tc, ca as u8))
+}
+
+fn request_aircraft(res: Res<ReadsbResource>, fetcher: Res<Fetcher<ReadsbResource>>) {
+    fetcher.fetch(ehttp::Request::get(&res.url));
+}
+
+fn handle_response(fetcher: Res<Fetcher<ReadsbResource>>, mut tracks: Tracks<AdsbAircraft>) {
+    for result in fetcher.responses() {
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                error!("aircraft.json request failed: {}", e);
+                continue;
+            }
+        };
+        let resp_json = match serde_json::from_slice::<AircraftResponse>(&response.bytes) {
+            Ok(resp_json) if response.ok => resp_json,
+            _ => {
+                error!(
+                    "aircraft.json request failed: {} {:?}",
+                    response.status,
+                    response.text()
+                );
+                continue;
+            }
+        };
+
+        tracks.update(resp_json.aircraft.into_iter().map(AdsbMessage::from));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adsb::AdsbKey;
+    use crate::network::mock::{http_server, response};
+    use crate::track::TrackIndex;
+
+    const AIRCRAFT_JSON: &str = r#"{
+        "now": 1717171717.1,
+        "messages": 123456,
+        "aircraft": [
+            {"hex": "3c6586", "type": "adsb_icao", "flight": "DLH9LF  ", "alt_baro": 38000,
+             "alt_geom": 38450, "gs": 452.3, "track": 87.5, "baro_rate": -64, "squawk": "1000",
+             "emergency": "none", "category": "A3", "lat": 50.1234, "lon": 8.5678,
+             "seen": 0.1, "rssi": -20.3},
+            {"hex": "~2a0f01", "type": "tisb_other", "alt_baro": "ground", "gs": 12.0,
+             "lat": 50.03, "lon": 8.56},
+            {"hex": "4ca7b1", "category": "B1", "emergency": "general"},
+            {"hex": "2a0f01", "lat": 50.5, "lon": 8.1}
+        ]
+    }"#;
+
+    fn messages() -> Vec<AdsbMessage> {
+        serde_json::from_str::<AircraftResponse>(AIRCRAFT_JSON)
+            .unwrap()
+            .aircraft
+            .into_iter()
+            .map(AdsbMessage::from)
+            .collect()
+    }
+
+    #[test]
+    fn airborne_aircraft() {
+        let message = &messages()[0];
+        assert_eq!(message.icao24, "3c6586");
+        assert_eq!(message.callsign.as_deref(), Some("DLH9LF"));
+        assert_eq!(message.baro_altitude, Some(38000.0 * FOOT));
+        assert_eq!(message.geo_altitude, Some(38450.0 * FOOT));
+        assert_eq!(message.on_ground, Some(false));
+        assert_eq!(message.ground_speed, Some(452.3 * KNOT));
+        assert_eq!(message.vertical_rate, Some(-64.0 * FEET_PER_MINUTE));
+        assert_eq!(message.category, Some(4));
+        assert_eq!(message.latitude, Some(50.1234));
+    }
+
+    #[test]
+    fn non_icao_address_on_ground() {
+        let message = &messages()[1];
+        assert_eq!(message.icao24, "~2a0f01");
+        assert_eq!(message.baro_altitude, None);
+        assert_eq!(message.on_ground, Some(true));
+        assert_eq!(message.callsign, None);
+    }
+
+    #[test]
+    fn missing_fields() {
+        let message = &messages()[2];
+        assert_eq!(message.on_ground, None);
+        assert_eq!(message.latitude, None);
+        // glider
+        assert_eq!(message.category, Some(9));
+        assert_eq!(message.emergency.as_deref(), Some("general"));
+    }
+
+    #[test]
+    fn polls_through_redirect() {
+        let url = http_server(|request| {
+            if request.starts_with("GET /aircraft.json ") {
+                response(
+                    "301 Moved Permanently",
+                    "Location: /data/aircraft.json\r\n",
+                    "",
+                )
+            } else {
+                response("200 OK", "", AIRCRAFT_JSON)
+            }
+        });
+        let mut app = App::new();
+        app.add_plugins(AdsbPlugin)
+            .init_resource::<Fetcher<ReadsbResource>>()
+            .add_systems(Update, handle_response);
+        app.world
+            .resource::<Fetcher<ReadsbResource>>()
+            .fetch(ehttp::Request::get(format!("{url}/aircraft.json")));
+
+        let started = std::time::Instant::now();
+        while app.world.resource::<TrackIndex<AdsbKey>>().len() < 4 {
+            assert!(started.elapsed() < Duration::from_secs(5), "no response");
+            std::thread::sleep(Duration::from_millis(10));
+            app.update();
+        }
+        // the TIS-B target does not take the place of the aircraft with the same ICAO address
+        let index = app.world.resource::<TrackIndex<AdsbKey>>();
+        assert_ne!(
+            index.get(&AdsbKey("~2a0f01".to_string())),
+            index.get(&AdsbKey("2a0f01".to_string()))
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use bevy::prelude::*;
+use bevy_octopus::prelude::*;
+
+use crate::adsb::{AdsbAircraft, AdsbMessage, AdsbPlugin};
+use crate::network::{log_connection, split_lines};
+use crate::track::Tracks;
+use crate::units::{FEET_PER_MINUTE, FOOT, KNOT};
+
+const SBS_CHANNEL: ChannelId = ChannelId("SBS");
+
+/// Reads a SBS-1 BaseStation feed, as served by dump1090 or readsb on port 30003.
+pub struct SbsPlugin {
+    /// address of the BaseStation feed, e.g. tcp://127.0.0.1:30003
+    pub address: String,
+}
+
+impl Plugin for SbsPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<AdsbPlugin>() {
+            app.add_plugins(AdsbPlugin);
+        }
+        app.insert_resource(SbsResource {
+            address: self.address.clone(),
+        })
+        .add_systems(Startup, setup)
+        .add_systems(Update, (log_connection(SBS_CHANNEL), handle_raw_packet));
+    }
+}
+
+#[derive(Resource)]
+pub struct SbsResource {
+    pub address: String,
+}
+
+/// setup the connection to the BaseStation feed
+fn setup(res: Res<SbsResource>, mut commands: Commands) {
+    commands.spawn((SBS_CHANNEL, ConnectTo::new(&res.address)));
+}
+
+/// Parse a `MSG,<type>,...` line, other message kinds (SEL, ID, AIR, STA, CLK) are ignored.
+fn parse(line: &str) -> Option<AdsbMessage> {
+    let fields = line.trim().split(',').collect::<Vec<_>>();
+    if fields.len() < 22 || fields[0] != "MSG" {
+        return None;
+    }
+    // transmission type 1..8
+    fields[1]
+        .parse::<u8>()
+        .ok()
+        .filter(|t| (1..=8).contains(t))?;
+    let icao24 = fields[4].trim().to_lowercase();
+    if icao24.is_empty() {
+        return None;
+    }
+    let text = |i: usize| {
+        let value = fields[i].trim();
+        (!value.is_empty()).then(|| value.to_string())
+    };
+    let number = |i: usize| fields[i].trim().parse::<f64>().ok();
+    // flags are sent as -1 (true) or 0 (false)
+    let flag = |i: usize| match fields[i].trim() {
+        "" => None,
+        value => Some(value != "0"),
+    };
+
+    Some(AdsbMessage {
+        icao24,
+        callsign: text(10),
+        baro_altitude: number(11).map(|feet| feet * FOOT),
+        ground_speed: number(12).map(|knots| knots * KNOT),
+        track: number(13),
+        latitude: number(14),
+        longitude: number(15),
+        vertical_rate: number(16).map(|fpm| fpm * FEET_PER_MINUTE),
+        squawk: text(17),
+        // the BaseStation format only has a flag
+        emergency: flag(19)
+            .map(|emergency| if emergency { "emergency" } else { "none" }.to_string()),
+        on_ground: flag(21),
+        ..default()
+    })
+}
+
+fn handle_raw_packet(
+    q_server: Query<(&ChannelId, &NetworkNode)>,
+    mut tracks: Tracks<AdsbAircraft>,
+    mut buffer: Local<Vec<u8>>,
+) {
+    let mut messages = vec![];
+    for (channel_id, net_node) in q_server.iter() {
+        if *channel_id != SBS_CHANNEL {
+            continue;
+        }
+        while let Ok(Some(packet)) = net_node.recv_message_channel.receiver.try_recv() {
+            for line in split_lines(&mut buffer, &packet.bytes) {
+                if let Some(msg) = parse(&line) {
+                    trace!("sbs message: {:?}", msg);
+                    messages.push(msg);
+                }
+            }
+        }
+    }
+    tracks.update(messages);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::TrackState;
+
+    const MSG_3: &str = "MSG,3,1,1,4CA2D6,1,2024/05/04,12:00:00.000,2024/05/04,12:00:00.000,,\
+                         37000,,,51.45735,-1.02826,,,0,0,0,0\r\n";
+    const MSG_4: &str = "MSG,4,1,1,4CA2D6,1,2024/05/04,12:00:00.100,2024/05/04,12:00:00.100,,,\
+                         451.0,291.0,,,-640,,,,,0";
+
+    #[test]
+    fn airborne_position() {
+        let message = parse(MSG_3).unwrap();
+        assert_eq!(message.icao24, "4ca2d6");
+        assert_eq!(message.latitude, Some(51.45735));
+        assert_eq!(message.longitude, Some(-1.02826));
+        assert_eq!(message.baro_altitude, Some(37000.0 * FOOT));
+        assert_eq!(message.emergency.as_deref(), Some("none"));
+        assert_eq!(message.on_ground, Some(false));
+        assert_eq!(message.ground_speed, None);
+    }
+
+    #[test]
+    fn airborne_velocity() {
+        let message = parse(MSG_4).unwrap();
+        assert_eq!(message.ground_speed, Some(451.0 * KNOT));
+        assert_eq!(message.track, Some(291.0));
+        assert_eq!(message.vertical_rate, Some(-640.0 * FEET_PER_MINUTE));
+        assert_eq!(message.latitude, None);
+        assert_eq!(message.emergency, None);
+    }
+
+    #[test]
+    fn messages_are_merged() {
+        let mut aircraft = AdsbAircraft::new(parse(MSG_3).unwrap()).unwrap();
+        aircraft.merge(parse(MSG_4).unwrap());
+        assert_eq!(aircraft.latitude, Some(51.45735));
+        assert_eq!(aircraft.baro_altitude, Some(37000.0 * FOOT));
+        assert_eq!(aircraft.track, Some(291.0));
+    }
+
+    #[test]
+    fn other_lines_are_ignored() {
+        assert_eq!(
+            parse("STA,,5,179,400AE7,10103,2008/11/28,14:58:51.153,2008/11/28,14:58:51.153,RM"),
+            None
+        );
+        assert_eq!(parse(&MSG_3.replace("MSG,3", "MSG,9")), None);
+        assert_eq!(parse(&MSG_3.replace("4CA2D6", "")), None);
+        assert_eq!(parse("MSG,3,1,1,4CA2D6"), None);
+    }
+}
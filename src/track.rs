@@ -0,0 +1,206 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use bevy::ecs::query::{QueryFilter, QueryItem, ReadOnlyQueryData};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::reflect::{GetTypeRegistration, TypePath};
+use bevy_activation::ActiveState;
+use bevy_tacview::record::{Coords, Property, PropertyList};
+use bevy_tacview::systems::ObjectNeedSync;
+
+/// Anything a source keeps per tracked object that can be shown in Tacview.
+///
+/// Sources only decode their feed into their own components and implement this trait, the
+/// [`TrackPlugin`] turns them into Tacview records, times them out and keeps them indexed by their
+/// [`TrackKey`].
+///
+/// An entity is only known to Tacview once it has a [`PropertyList`], until then it is spawned on
+/// its first change that finds it [`ready`](TrackSource::ready).
+pub trait TrackSource: Send + Sync + 'static {
+    /// identifies the objects of the source, sources sharing a key share one [`TrackIndex`]
+    type Key: TrackKey;
+    /// components the Tacview record is built from
+    type Data: ReadOnlyQueryData;
+    /// matches the entities whose data was added or changed
+    type Changed: QueryFilter;
+
+    /// remove the object from Tacview after this long without a change, `None` keeps it
+    const TIMEOUT: Option<Duration>;
+
+    fn coords(data: &QueryItem<Self::Data>) -> Coords;
+
+    fn props(data: &QueryItem<Self::Data>) -> Vec<Property>;
+
+    /// Whether the object can be shown yet, e.g. once it has a position. Objects that are not
+    /// ready still time out, their first change after becoming ready spawns them.
+    fn ready(_data: &QueryItem<Self::Data>) -> bool {
+        true
+    }
+}
+
+/// Identifies a tracked object across the messages of its source, e.g. its ICAO24 address. The
+/// key is a component of the object so the index can drop it once the object is despawned.
+pub trait TrackKey:
+    Component + Clone + Eq + Hash + Debug + Reflect + FromReflect + TypePath + GetTypeRegistration
+{
+}
+
+/// Object entities by their key.
+#[derive(Resource, Deref, DerefMut, Reflect)]
+pub struct TrackIndex<K: TrackKey>(HashMap<K, Entity>);
+
+impl<K: TrackKey> Default for TrackIndex<K> {
+    fn default() -> Self {
+        Self(HashMap::new())
+    }
+}
+
+/// State of an object that lives in one component and is built from the messages of its source,
+/// see [`Tracks::update`].
+pub trait TrackState: Component + Clone + PartialEq {
+    type Key: TrackKey;
+    type Message;
+
+    /// key of the object the message is about
+    fn key(message: &Self::Message) -> Self::Key;
+
+    /// State of an object from its first message, `None` if the message alone does not make an
+    /// object and is dropped.
+    fn new(message: Self::Message) -> Option<Self>;
+
+    /// merge a later message into the state
+    fn merge(&mut self, message: Self::Message);
+}
+
+/// Spawns and updates the objects of a [`TrackState`] by their key.
+#[derive(SystemParam)]
+pub struct Tracks<'w, 's, C: TrackState> {
+    index: ResMut<'w, TrackIndex<<C as TrackState>::Key>>,
+    query: Query<'w, 's, &'static mut C>,
+    pub commands: Commands<'w, 's>,
+}
+
+impl<C: TrackState> Tracks<'_, '_, C> {
+    /// Apply the messages to the objects they are about and return the objects spawned for the
+    /// keys seen for the first time.
+    pub fn update(&mut self, messages: impl IntoIterator<Item = C::Message>) -> Vec<Entity> {
+        // objects spawned by these messages, only in the world once the commands are applied
+        let mut spawned: HashMap<C::Key, C> = HashMap::new();
+        for message in messages {
+            let key = C::key(&message);
+            if let Some(state) = spawned.get_mut(&key) {
+                state.merge(message);
+                continue;
+            }
+            match self
+                .index
+                .get(&key)
+                .and_then(|entity| self.query.get_mut(*entity).ok())
+            {
+                Some(mut state) => {
+                    let mut new_state = state.clone();
+                    new_state.merge(message);
+                    state.set_if_neq(new_state);
+                }
+                None => {
+                    if let Some(state) = C::new(message) {
+                        spawned.insert(key, state);
+                    }
+                }
+            }
+        }
+
+        spawned
+            .into_iter()
+            .map(|(key, state)| {
+                let entity = self.commands.spawn((key.clone(), state)).id();
+                self.index.insert(key, entity);
+                entity
+            })
+            .collect()
+    }
+
+    pub fn get(&self, key: &C::Key) -> Option<Entity> {
+        self.index.get(key).copied()
+    }
+}
+
+/// Syncs the objects of one [`TrackSource`] to Tacview.
+pub struct TrackPlugin<S: TrackSource>(PhantomData<S>);
+
+impl<S: TrackSource> Default for TrackPlugin<S> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<S: TrackSource> Plugin for TrackPlugin<S> {
+    fn build(&self, app: &mut App) {
+        if !app.world.contains_resource::<TrackIndex<S::Key>>() {
+            app.init_resource::<TrackIndex<S::Key>>()
+                .register_type::<S::Key>()
+                .register_type::<TrackIndex<S::Key>>()
+                .add_systems(Update, remove_despawned::<S::Key>);
+        }
+        app.add_systems(Update, watch_changed::<S>);
+    }
+}
+
+/// drop despawned objects from the index
+fn remove_despawned<K: TrackKey>(
+    mut removed: RemovedComponents<K>,
+    mut index: ResMut<TrackIndex<K>>,
+) {
+    let removed = removed.read().collect::<HashSet<_>>();
+    if !removed.is_empty() {
+        index.retain(|_, entity| !removed.contains(entity));
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn watch_changed<S: TrackSource>(
+    mut query: Query<
+        (
+            Entity,
+            S::Data,
+            Option<&mut Coords>,
+            Option<&mut PropertyList>,
+            Option<&mut ActiveState>,
+        ),
+        S::Changed,
+    >,
+    mut commands: Commands,
+) {
+    for (entity, data, coords, props_list, active_state) in query.iter_mut() {
+        match active_state {
+            Some(mut active_state) => active_state.toggle(),
+            None => {
+                trace!("Added {}: {:?}", std::any::type_name::<S>(), entity);
+                commands.entity(entity).insert(match S::TIMEOUT {
+                    Some(timeout) => ActiveState::new(timeout),
+                    None => ActiveState::always(),
+                });
+            }
+        }
+
+        match (coords, props_list) {
+            (Some(mut coords), Some(mut props_list)) => {
+                coords.set_if_neq(S::coords(&data));
+                props_list.set_if_neq(PropertyList(S::props(&data)));
+                commands.entity(entity).insert(ObjectNeedSync::Update);
+            }
+            _ if S::ready(&data) => {
+                commands.entity(entity).insert((
+                    S::coords(&data),
+                    PropertyList(S::props(&data)),
+                    ObjectNeedSync::Spawn,
+                ));
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,8 @@
+//! Conversions of the aviation and nautical units the feeds report to SI units.
+
+/// meters in one foot
+pub const FOOT: f64 = 0.3048;
+/// meters per second in one knot
+pub const KNOT: f64 = 0.514444;
+/// meters per second in one foot per minute
+pub const FEET_PER_MINUTE: f64 = 0.00508;